@@ -0,0 +1,132 @@
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use alsa::pcm::{Access, Format, HwParams, PCM};
+use alsa::{Direction, ValueOr};
+
+use crate::audio::{
+    capture_interleaved, new_streams, AudioBackend, Ports, Session, Status, DEFAULT_SAMPLE_RATE,
+};
+use crate::dsp::{StreamFormat, StreamStats};
+
+/// Name of the backend, shown in the UI
+const NAME: &str = "ALSA";
+
+/// Device that is opened if none was given
+const DEFAULT_DEVICE: &str = "default";
+
+/// How long to wait before trying to open the device again
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the status is updated while capturing
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Period size that is asked for, the device may pick another one
+const PERIOD_SIZE: usize = 1024;
+
+/// Captures from an ALSA PCM device, like a sound card, `null` or a `snd-aloop` loopback
+///
+/// The device gets reopened if it fails, e.g. because a USB interface was unplugged. ALSA has
+/// no ports to connect, so the port picker stays empty.
+pub struct AlsaBackend {
+    device: String,
+}
+
+impl AlsaBackend {
+    pub fn new(device: Option<String>) -> Self {
+        AlsaBackend {
+            device: device.unwrap_or_else(|| DEFAULT_DEVICE.to_string()),
+        }
+    }
+}
+
+impl AudioBackend for AlsaBackend {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn start(self: Box<Self>, channels: Vec<String>, mut session: Session) {
+        thread::spawn(move || loop {
+            session.set_status(Status::Waiting(NAME));
+            session.set_ports(Ports::default());
+
+            if let Ok(pcm) = open(&self.device, channels.len()) {
+                if capture(&pcm, channels.len(), &mut session).is_err() {
+                    eprintln!("Lost the ALSA device {}, reopening it.", self.device);
+                }
+            }
+
+            // A device that keeps failing right away would turn this into a busy loop
+            thread::sleep(RETRY_INTERVAL);
+        });
+    }
+}
+
+/// Opens the device for interleaved float capture with the given amount of channels
+fn open(device: &str, channels: usize) -> Result<PCM, alsa::Error> {
+    let pcm = PCM::new(device, Direction::Capture, false)?;
+
+    {
+        let hw_params = HwParams::any(&pcm)?;
+        hw_params.set_channels(channels as u32)?;
+        hw_params.set_rate_near(DEFAULT_SAMPLE_RATE as u32, ValueOr::Nearest)?;
+        hw_params.set_format(Format::float())?;
+        hw_params.set_access(Access::RWInterleaved)?;
+        hw_params.set_period_size_near(PERIOD_SIZE as alsa::pcm::Frames, ValueOr::Nearest)?;
+        pcm.hw_params(&hw_params)?;
+    }
+
+    pcm.start()?;
+
+    Ok(pcm)
+}
+
+/// Reads from the device until it fails in a way that can't be recovered from
+fn capture(pcm: &PCM, channels: usize, session: &mut Session) -> Result<(), alsa::Error> {
+    let hw_params = pcm.hw_params_current()?;
+    let sample_rate = hw_params.get_rate()? as usize;
+    let period_size = hw_params.get_period_size()? as usize;
+
+    let format = Arc::new(StreamFormat::new(sample_rate, period_size));
+    let stats = Arc::new(StreamStats::default());
+
+    let (mut producers, streams) = new_streams(channels, &format, &stats);
+    (session.on_connect)(streams);
+
+    let io = pcm.io_f32()?;
+    let mut buffer = vec![0.; period_size * channels];
+    let mut last_status = Instant::now() - POLL_INTERVAL;
+
+    loop {
+        match io.readi(&mut buffer) {
+            Ok(frames) => {
+                capture_interleaved(
+                    buffer[..frames * channels].iter().copied(),
+                    &mut producers,
+                    &stats,
+                );
+            }
+            // Reading too late overruns the device, which has to be restarted
+            Err(e) => {
+                stats.add_xrun();
+                pcm.try_recover(e, true)?;
+            }
+        }
+
+        // Nothing sends port commands to ALSA, so they are just dropped
+        while session.commands.try_recv().is_ok() {}
+
+        if last_status.elapsed() >= POLL_INTERVAL {
+            last_status = Instant::now();
+            session.set_status(Status::Connected {
+                backend: NAME,
+                sample_rate: format.sample_rate(),
+                buffer_size: format.buffer_size(),
+                xruns: stats.xruns(),
+                overruns: stats.overruns(),
+                resyncs: stats.resyncs(),
+            });
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::fmt;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+
+use regex::Regex;
+use ringbuf::{Producer, RingBuffer};
+
+use crate::dsp::{Stream, StreamFormat, StreamStats};
+
+/// Capacity of the ringbuffer between the backend and the DSP of every channel
+const RINGBUFFER_SIZE: usize = 50_000;
+
+/// Sample rate that is assumed until the server tells the real one
+pub const DEFAULT_SAMPLE_RATE: usize = 48_000;
+
+/// State of the connection to the audio server, shown in the UI
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Status {
+    /// Waiting for the server of the named backend
+    Waiting(&'static str),
+    Connected {
+        backend: &'static str,
+        sample_rate: usize,
+        buffer_size: usize,
+        xruns: usize,
+        overruns: usize,
+        resyncs: usize,
+    },
+    /// Playing a file, the position and the duration are in seconds
+    Playback {
+        sample_rate: usize,
+        position: f32,
+        duration: f32,
+        paused: bool,
+    },
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Status::Waiting(backend) => write!(f, "Waiting for {}...", backend),
+            Status::Connected {
+                backend,
+                sample_rate,
+                buffer_size,
+                xruns,
+                overruns,
+                resyncs,
+            } => write!(
+                f,
+                "{} @ {} Hz, {} Samples, {} Xruns, {} Overruns, {} Resyncs",
+                backend, sample_rate, buffer_size, xruns, overruns, resyncs
+            ),
+            Status::Playback {
+                sample_rate,
+                position,
+                duration,
+                paused,
+            } => write!(
+                f,
+                "{} / {} @ {} Hz{}",
+                Timestamp(*position),
+                Timestamp(*duration),
+                sample_rate,
+                if *paused { ", Paused" } else { "" }
+            ),
+        }
+    }
+}
+
+/// Formats seconds as minutes and seconds, like 3:07
+struct Timestamp(f32);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.0.max(0.) as usize;
+        write!(f, "{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// Selects source ports by their full name, either exactly or with a regular expression
+pub struct PortPattern {
+    name: String,
+    /// None if the pattern is no valid expression, like a port name with unbalanced brackets
+    regex: Option<Regex>,
+}
+
+impl PortPattern {
+    pub fn new(pattern: &str) -> Self {
+        PortPattern {
+            name: pattern.to_string(),
+            regex: Regex::new(&format!("^(?:{})$", pattern)).ok(),
+        }
+    }
+
+    pub fn matches(&self, port_name: &str) -> bool {
+        port_name == self.name
+            || self
+                .regex
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(port_name))
+    }
+}
+
+/// The audio outputs of the server and what they are connected to, shown in the port picker
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Ports {
+    /// Full names of the analyzer inputs
+    pub inputs: Vec<String>,
+    /// Full names of all audio outputs that could feed the inputs
+    pub sources: Vec<String>,
+    /// The sources connected to each input
+    pub connections: Vec<Vec<String>>,
+}
+
+impl Ports {
+    pub fn is_connected(&self, source: &str, input: usize) -> bool {
+        self.connections
+            .get(input)
+            .is_some_and(|connections| connections.iter().any(|e| e == source))
+    }
+}
+
+/// A change to the connections the user asked for in the port picker
+pub enum PortCommand {
+    Connect { source: String, input: usize },
+    Disconnect { source: String, input: usize },
+}
+
+/// The parts of the app a backend reports to and takes commands from
+pub struct Session {
+    pub status: Arc<Mutex<Status>>,
+    pub ports: Arc<Mutex<Ports>>,
+    pub commands: Receiver<PortCommand>,
+    /// Takes the new stream of every channel, in the order of the channel names, whenever the
+    /// backend (re)connects
+    pub on_connect: Box<dyn FnMut(Vec<Stream>) + Send>,
+}
+
+impl Session {
+    pub fn set_status(&self, status: Status) {
+        if let Ok(mut shared) = self.status.lock() {
+            *shared = status;
+        }
+    }
+
+    pub fn set_ports(&self, ports: Ports) {
+        if let Ok(mut shared) = self.ports.lock() {
+            *shared = ports;
+        }
+    }
+}
+
+/// A source of audio the analyzer can capture from
+///
+/// The rest of the app only sees the streams and the session, never the types of the backend.
+pub trait AudioBackend {
+    /// Shown in the UI, e.g. while waiting for the server
+    fn name(&self) -> &'static str;
+
+    /// Starts capturing the named channels in the background
+    fn start(self: Box<Self>, channels: Vec<String>, session: Session);
+}
+
+/// A ringbuffer per channel, the producers go to the backend and the streams to the DSP
+pub fn new_streams(
+    channels: usize,
+    format: &Arc<StreamFormat>,
+    stats: &Arc<StreamStats>,
+) -> (Vec<Producer<f32>>, Vec<Stream>) {
+    (0..channels)
+        .map(|_| {
+            let (producer, consumer) = RingBuffer::<f32>::new(RINGBUFFER_SIZE).split();
+            let stream = Stream {
+                consumer,
+                format: format.clone(),
+                stats: stats.clone(),
+            };
+            (producer, stream)
+        })
+        .unzip()
+}
+
+/// Copies one period of every channel into the ringbuffer of its channel
+///
+/// Meant for the real-time thread of a backend, it only copies whole slices and counts with
+/// atomics, nothing in here allocates, locks or waits.
+pub fn capture_period<'a>(
+    periods: impl Iterator<Item = &'a [f32]>,
+    producers: &mut [Producer<f32>],
+    stats: &StreamStats,
+) {
+    for (period, producer) in periods.zip(producers.iter_mut()) {
+        // A full ringbuffer means the DSP fell behind, the rest of the period gets dropped
+        if producer.push_slice(period) < period.len() {
+            stats.add_overrun();
+        }
+    }
+}
+
+/// Splits interleaved samples into the ringbuffers of the channels
+///
+/// Meant for the real-time thread of a backend, it neither allocates nor locks. A partial
+/// frame at the end gets dropped, it would shift the channels against each other.
+pub fn capture_interleaved(
+    samples: impl ExactSizeIterator<Item = f32> + Clone,
+    producers: &mut [Producer<f32>],
+    stats: &StreamStats,
+) {
+    let channels = producers.len();
+    let frames = samples.len() / channels.max(1);
+
+    for (channel, producer) in producers.iter_mut().enumerate() {
+        let mut channel_samples = samples.clone().skip(channel).step_by(channels).take(frames);
+        producer.push_iter(&mut channel_samples);
+
+        // A full ringbuffer means the DSP fell behind, the rest gets dropped
+        if channel_samples.next().is_some() {
+            stats.add_overrun();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ringbuf::RingBuffer;
+
+    use super::{capture_interleaved, capture_period};
+    use crate::dsp::StreamStats;
+
+    #[test]
+    fn periods_arrive_in_order() {
+        let stats = StreamStats::default();
+        let (mut producers, mut consumers): (Vec<_>, Vec<_>) =
+            (0..2).map(|_| RingBuffer::<f32>::new(16).split()).unzip();
+
+        let left = [1., 2., 3., 4.];
+        let right = [-1., -2., -3., -4.];
+        capture_period([&left[..], &right[..]].into_iter(), &mut producers, &stats);
+        capture_period(
+            [&left[2..], &right[2..]].into_iter(),
+            &mut producers,
+            &stats,
+        );
+
+        let mut received = [0.; 6];
+        assert_eq!(consumers[0].pop_slice(&mut received), 6);
+        assert_eq!(received, [1., 2., 3., 4., 3., 4.]);
+        assert_eq!(consumers[1].pop_slice(&mut received), 6);
+        assert_eq!(received, [-1., -2., -3., -4., -3., -4.]);
+        assert_eq!(stats.overruns(), 0);
+    }
+
+    #[test]
+    fn full_ringbuffer_drops_the_rest_of_the_period() {
+        let stats = StreamStats::default();
+        let (producer, mut consumer) = RingBuffer::<f32>::new(6).split();
+        let mut producers = vec![producer];
+
+        let period = [1., 2., 3., 4.];
+        capture_period([&period[..]].into_iter(), &mut producers, &stats);
+        assert_eq!(stats.overruns(), 0);
+
+        // Only two samples fit, the other two are lost
+        capture_period([&period[..]].into_iter(), &mut producers, &stats);
+        assert_eq!(stats.overruns(), 1);
+
+        // Nothing fits anymore, which is still one overrun per period
+        capture_period([&period[..]].into_iter(), &mut producers, &stats);
+        assert_eq!(stats.overruns(), 2);
+
+        let mut received = [0.; 8];
+        assert_eq!(consumer.pop_slice(&mut received), 6);
+        assert_eq!(received[..6], [1., 2., 3., 4., 1., 2.]);
+    }
+
+    #[test]
+    fn interleaved_samples_are_split_into_channels() {
+        let stats = StreamStats::default();
+        let (mut producers, mut consumers): (Vec<_>, Vec<_>) =
+            (0..3).map(|_| RingBuffer::<f32>::new(16).split()).unzip();
+
+        let samples = [1., 10., 100., 2., 20., 200.];
+        capture_interleaved(samples.iter().copied(), &mut producers, &stats);
+
+        let mut received = [0.; 2];
+        for (consumer, expected) in consumers
+            .iter_mut()
+            .zip([[1., 2.], [10., 20.], [100., 200.]])
+        {
+            assert_eq!(consumer.pop_slice(&mut received), 2);
+            assert_eq!(received, expected);
+        }
+        assert_eq!(stats.overruns(), 0);
+    }
+
+    #[test]
+    fn partial_frame_is_dropped() {
+        let stats = StreamStats::default();
+        let (mut producers, mut consumers): (Vec<_>, Vec<_>) =
+            (0..2).map(|_| RingBuffer::<f32>::new(16).split()).unzip();
+
+        // The last sample has no partner in the right channel
+        let samples = [1., -1., 2., -2., 3.];
+        capture_interleaved(samples.iter().copied(), &mut producers, &stats);
+        let samples = [4., -4.];
+        capture_interleaved(samples.iter().copied(), &mut producers, &stats);
+
+        let mut received = [0.; 4];
+        assert_eq!(consumers[0].pop_slice(&mut received), 3);
+        assert_eq!(received[..3], [1., 2., 4.]);
+        assert_eq!(consumers[1].pop_slice(&mut received), 3);
+        assert_eq!(received[..3], [-1., -2., -4.]);
+    }
+
+    #[test]
+    fn full_ringbuffer_counts_an_overrun_per_channel() {
+        let stats = StreamStats::default();
+        let (mut producers, mut consumers): (Vec<_>, Vec<_>) =
+            (0..2).map(|_| RingBuffer::<f32>::new(3).split()).unzip();
+
+        let samples = [1., -1., 2., -2.];
+        capture_interleaved(samples.iter().copied(), &mut producers, &stats);
+        assert_eq!(stats.overruns(), 0);
+
+        // Only one more frame fits, the second one is lost in both channels
+        capture_interleaved(samples.iter().copied(), &mut producers, &stats);
+        assert_eq!(stats.overruns(), 2);
+
+        let mut received = [0.; 4];
+        assert_eq!(consumers[0].pop_slice(&mut received), 3);
+        assert_eq!(received[..3], [1., 2., 1.]);
+        assert_eq!(consumers[1].pop_slice(&mut received), 3);
+        assert_eq!(received[..3], [-1., -2., -1.]);
+    }
+}
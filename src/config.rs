@@ -0,0 +1,141 @@
+use std::path::PathBuf;
+
+use crate::audio::PortPattern;
+use crate::dsp::Overlap;
+
+/// The audio backends that can be picked with `--backend`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Driver {
+    Jack,
+    #[cfg(feature = "alsa")]
+    Alsa,
+    #[cfg(feature = "pipewire")]
+    PipeWire,
+}
+
+impl Driver {
+    /// Parses the name of a backend, if it was compiled in
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "jack" => Some(Driver::Jack),
+            #[cfg(feature = "alsa")]
+            "alsa" => Some(Driver::Alsa),
+            #[cfg(feature = "pipewire")]
+            "pipewire" => Some(Driver::PipeWire),
+            _ => None,
+        }
+    }
+
+    /// Names of all backends that were compiled in
+    pub fn names() -> Vec<&'static str> {
+        vec![
+            "jack",
+            #[cfg(feature = "alsa")]
+            "alsa",
+            #[cfg(feature = "pipewire")]
+            "pipewire",
+        ]
+    }
+}
+
+/// Runtime options, parsed from the command line
+pub struct Config {
+    /// Amount of input ports that get registered and analyzed
+    pub channels: usize,
+    /// Analyze an L/R pair with mid, side and correlation instead of independent channels
+    pub stereo: bool,
+    /// Overlap between consecutive analysis frames
+    pub overlap: Overlap,
+    /// Source ports that get connected to the inputs whenever they exist
+    pub sources: Vec<PortPattern>,
+    /// Audio backend to capture from
+    pub driver: Driver,
+    /// Device to capture from, the PCM name for ALSA or the target node for PipeWire
+    pub device: Option<String>,
+    /// Audio file to analyze instead of capturing from a backend
+    pub file: Option<PathBuf>,
+    /// Analyze the file as fast as possible instead of playing it in real time
+    pub fast: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            channels: 1,
+            stereo: false,
+            overlap: Overlap::ThreeQuarters,
+            sources: Vec::new(),
+            driver: Driver::Jack,
+            device: None,
+            file: None,
+            fast: false,
+        }
+    }
+}
+
+impl Config {
+    /// Parses the process arguments
+    ///
+    /// Supported options:
+    /// `--channels <n>`: Register n input ports named fourier_in_1..n
+    /// `--stereo`: Register fourier_in_l and fourier_in_r and analyze them as a stereo pair,
+    /// can't be combined with `--channels`
+    /// `--overlap <percent>`: Overlap of the analysis frames, one of 0, 50, 75 or 87.5
+    /// `--connect <port>`: Connect a source port, given by its full name or a regular expression,
+    /// can be repeated. The matches are distributed over the inputs in order.
+    /// `--backend <name>`: Capture from jack (the default), alsa or pipewire, if compiled in
+    /// `--device <name>`: The ALSA device like hw:1 or the PipeWire node to capture from
+    /// `--file <path>`: Analyze a WAV, FLAC or AIFF file instead of capturing audio
+    /// `--fast`: Analyze the file as fast as possible instead of playing it in real time
+    pub fn from_args() -> Self {
+        let mut config = Config::default();
+        let mut args = std::env::args().skip(1);
+        let mut channels_given = false;
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--channels" | "-c" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) if n > 0 => {
+                        config.channels = n;
+                        channels_given = true;
+                    }
+                    _ => eprintln!("--channels expects a number greater than 0."),
+                },
+                "--stereo" | "-s" => config.stereo = true,
+                "--overlap" | "-o" => match args.next().and_then(|n| Overlap::from_percent(&n)) {
+                    Some(overlap) => config.overlap = overlap,
+                    None => eprintln!("--overlap expects one of 0, 50, 75 or 87.5."),
+                },
+                "--connect" | "-p" => match args.next() {
+                    Some(pattern) => config.sources.push(PortPattern::new(&pattern)),
+                    None => eprintln!("--connect expects a port name or pattern."),
+                },
+                "--backend" | "-b" => match args.next().and_then(|n| Driver::from_name(&n)) {
+                    Some(driver) => config.driver = driver,
+                    None => eprintln!("--backend expects one of {}.", Driver::names().join(", ")),
+                },
+                "--device" | "-d" => match args.next() {
+                    Some(device) => config.device = Some(device),
+                    None => eprintln!("--device expects a device name."),
+                },
+                "--file" | "-f" => match args.next() {
+                    Some(path) => config.file = Some(PathBuf::from(path)),
+                    None => eprintln!("--file expects the path of an audio file."),
+                },
+                "--fast" => config.fast = true,
+                _ => eprintln!("Unknown argument {}, ignoring it.", arg),
+            }
+        }
+
+        // A stereo pair always has two inputs, so asking for another amount is most likely a mistake
+        if config.stereo && channels_given {
+            eprintln!(
+                "--stereo always analyzes 2 channels, ignoring --channels {}.",
+                config.channels
+            );
+            config.channels = 2;
+        }
+
+        config
+    }
+}
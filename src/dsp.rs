@@ -1,88 +1,672 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, TryRecvError};
 use std::sync::{Arc, Mutex};
 use std::thread;
+use std::time::Duration;
+
+use self::triple_buffer::Input;
 
 use crate::{BUFFER_SIZE, FFT_SIZE};
 use ringbuf::Consumer;
 use rustfft::num_complex::Complex;
 use rustfft::num_traits::Pow;
-use rustfft::FftPlanner;
 
-pub fn process_thread(mut consumer: Consumer<f32>, delivery_mutex: Arc<Mutex<Vec<f32>>>) {
+pub use self::analyzer::Analyzer;
+pub use self::frame::SpectrumFrame;
+pub use self::window::WindowFunction;
+
+mod analyzer;
+mod cqt;
+mod fft;
+mod frame;
+mod multires;
+pub mod triple_buffer;
+mod window;
+
+/// Shortest time the DSP sleeps while waiting for samples, so it doesn't spin on tiny gaps
+const MIN_WAIT: Duration = Duration::from_millis(1);
+
+/// How much of the old cross spectra is kept each frame for the correlation estimate
+const CORRELATION_SMOOTHING: f32 = 0.8;
+
+/// The transform a frame gets analyzed with
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backend {
+    /// Linearly spaced bins from a zero padded FFT
+    Fft,
+    /// Log spaced bins with the given amount of bins per octave
+    ConstantQ(usize),
+    /// FFTs at the full, a quarter and a sixteenth of the sample rate, stitched together
+    MultiResolution,
+}
+
+/// Analysis parameters that can be changed while the DSP is running
+#[derive(Clone, Copy, PartialEq)]
+pub struct AnalysisSettings {
+    pub backend: Backend,
+    /// Amount of samples in one analysis frame, also sets the hop size of the constant-Q transform
+    pub window_size: usize,
+    /// Factor the frame is zero padded by before the FFT, the constant-Q transform doesn't pad
+    pub padding: usize,
+    pub window: WindowFunction,
+}
+
+impl Default for AnalysisSettings {
+    fn default() -> Self {
+        AnalysisSettings {
+            backend: Backend::Fft,
+            window_size: BUFFER_SIZE,
+            padding: FFT_SIZE / BUFFER_SIZE,
+            window: WindowFunction::Hann,
+        }
+    }
+}
+
+impl AnalysisSettings {
+    pub const MIN_WINDOW_SIZE: usize = 512;
+    pub const MAX_WINDOW_SIZE: usize = 32768;
+    pub const MAX_PADDING: usize = 8;
+
+    pub fn fft_size(&self) -> usize {
+        self.window_size * self.padding
+    }
+
+    /// How many bins the power of a sine spreads over
+    ///
+    /// Dividing the summed power of the bins in a band by this gives the power of the signal
+    /// in that band, no matter if it is a tone or noise. Zero padding puts more bins into the
+    /// same bandwidth, the constant-Q transform doesn't pad and its bins are spaced by their
+    /// bandwidth.
+    pub fn bins_per_tone(&self) -> f32 {
+        let padding = match self.backend {
+            Backend::ConstantQ(_) => 1,
+            Backend::Fft | Backend::MultiResolution => self.padding,
+        };
+
+        self.window.enbw(self.window_size) * padding as f32
+    }
+
+    /// Reads the settings without waiting for the UI
+    ///
+    /// Keeps the current settings if the UI is holding the lock right now or panicked while holding it.
+    fn read(shared: &Mutex<AnalysisSettings>, current: AnalysisSettings) -> Self {
+        match shared.try_lock() {
+            Ok(settings) => *settings,
+            Err(_) => current,
+        }
+    }
+}
+
+/// How much consecutive analysis frames overlap
+#[derive(Clone, Copy)]
+pub enum Overlap {
+    None,
+    Half,
+    ThreeQuarters,
+    SevenEighths,
+}
+
+impl Overlap {
+    /// Parses a percentage like "75" or "87.5"
+    pub fn from_percent(percent: &str) -> Option<Self> {
+        match percent.trim_end_matches('%') {
+            "0" => Some(Overlap::None),
+            "50" => Some(Overlap::Half),
+            "75" => Some(Overlap::ThreeQuarters),
+            "87.5" => Some(Overlap::SevenEighths),
+            _ => None,
+        }
+    }
+
+    /// The amount of new samples between two frames of the given size
+    pub fn hop_size(&self, frame_size: usize) -> usize {
+        match self {
+            Overlap::None => frame_size,
+            Overlap::Half => frame_size / 2,
+            Overlap::ThreeQuarters => frame_size / 4,
+            Overlap::SevenEighths => frame_size / 8,
+        }
+    }
+}
+
+/// Holds the newest samples of a channel, so the tail of a frame can be reused by the next one
+struct SlidingWindow {
+    samples: Vec<f32>,
+    hop: usize,
+}
+
+impl SlidingWindow {
+    fn new(size: usize, hop: usize) -> Self {
+        SlidingWindow {
+            samples: vec![0.; size],
+            hop: hop.min(size),
+        }
+    }
+
+    /// Whether the ringbuffer holds enough samples for the next frame
+    fn ready(&self, consumer: &Consumer<f32>) -> bool {
+        consumer.len() >= self.hop
+    }
+
+    /// How long JACK needs to deliver the samples that are still missing for the next frame
+    fn wait_time(&self, consumer: &Consumer<f32>, format: &StreamFormat) -> Duration {
+        let missing = self.hop.saturating_sub(consumer.len());
+
+        // Samples arrive one period at a time, so waking up in between is pointless
+        let buffer_size = format.buffer_size().max(1);
+        let missing = missing.div_ceil(buffer_size) * buffer_size;
+
+        Duration::from_secs_f32(missing as f32 / format.sample_rate() as f32).max(MIN_WAIT)
+    }
+
+    /// Whether the ringbuffer holds more than the next frame and a period, so the analysis fell behind
+    ///
+    /// Frames that are longer than the ringbuffer can never have that much waiting, those lag
+    /// once the ringbuffer has no room left for another period.
+    /// An offline stream never lags, its backend waits for the DSP instead of dropping samples.
+    fn lagging(&self, consumer: &Consumer<f32>, format: &StreamFormat) -> bool {
+        let backlog = (self.samples.len() + self.hop + format.buffer_size())
+            .min(consumer.capacity().saturating_sub(format.buffer_size()));
+        format.live() && consumer.len() > backlog
+    }
+
+    /// Skips everything but the newest samples, which fill as much of the frame as they can
+    ///
+    /// A frame longer than the ringbuffer keeps its oldest samples in front of the new ones.
+    fn resync(&mut self, consumer: &mut Consumer<f32>) {
+        let size = self.samples.len();
+        let stale = consumer.len().saturating_sub(size);
+        consumer.discard(stale);
+
+        let fresh = consumer.len();
+        self.samples.rotate_left(fresh);
+        consumer.pop_slice(&mut self.samples[size - fresh..]);
+    }
+
+    /// Drops the oldest hop samples and appends hop new ones from the ringbuffer
+    fn advance(&mut self, consumer: &mut Consumer<f32>) {
+        let size = self.samples.len();
+        self.samples.rotate_left(self.hop);
+        consumer.pop_slice(&mut self.samples[size - self.hop..]);
+    }
+}
+
+impl SlidingWindow {
+    /// A sliding window that holds enough samples for the analyzer and moves by the overlap of the window size
+    fn for_analyzer(analyzer: &Analyzer, settings: &AnalysisSettings, overlap: Overlap) -> Self {
+        SlidingWindow::new(
+            analyzer.frame_size(),
+            overlap.hop_size(settings.window_size),
+        )
+    }
+}
+
+/// The samples of one channel from the current connection to the audio server
+///
+/// Every (re)connection hands over a new stream, the old one just stops receiving samples.
+pub struct Stream {
+    pub consumer: Consumer<f32>,
+    pub format: Arc<StreamFormat>,
+    pub stats: Arc<StreamStats>,
+}
+
+/// Sample rate and period size of a stream, the audio server may change both while it runs
+pub struct StreamFormat {
+    sample_rate: AtomicUsize,
+    buffer_size: AtomicUsize,
+    /// Whether the samples arrive at the pace of the audio hardware
+    live: bool,
+}
+
+impl StreamFormat {
+    pub fn new(sample_rate: usize, buffer_size: usize) -> Self {
+        StreamFormat {
+            sample_rate: AtomicUsize::new(sample_rate),
+            buffer_size: AtomicUsize::new(buffer_size),
+            live: true,
+        }
+    }
+
+    /// A stream that gets fed as fast as the DSP takes the samples, like a file being analyzed
+    pub fn offline(sample_rate: usize, buffer_size: usize) -> Self {
+        StreamFormat {
+            live: false,
+            ..StreamFormat::new(sample_rate, buffer_size)
+        }
+    }
+
+    pub fn live(&self) -> bool {
+        self.live
+    }
+
+    pub fn sample_rate(&self) -> usize {
+        self.sample_rate.load(Ordering::Acquire)
+    }
+
+    /// The amount of samples per channel the server delivers at once
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.load(Ordering::Acquire)
+    }
+
+    pub fn set_sample_rate(&self, sample_rate: usize) {
+        self.sample_rate.store(sample_rate, Ordering::Release);
+    }
+
+    pub fn set_buffer_size(&self, buffer_size: usize) {
+        self.buffer_size.store(buffer_size, Ordering::Release);
+    }
+}
+
+/// Trouble on the way from the audio server to the analysis, counted since connecting
+#[derive(Default)]
+pub struct StreamStats {
+    /// Cycles the server couldn't finish in time
+    xruns: AtomicUsize,
+    /// Cycles in which a full ringbuffer dropped samples
+    overruns: AtomicUsize,
+    /// Times the DSP fell behind and skipped to the newest samples
+    resyncs: AtomicUsize,
+}
+
+impl StreamStats {
+    pub fn xruns(&self) -> usize {
+        self.xruns.load(Ordering::Relaxed)
+    }
+
+    pub fn overruns(&self) -> usize {
+        self.overruns.load(Ordering::Relaxed)
+    }
+
+    pub fn resyncs(&self) -> usize {
+        self.resyncs.load(Ordering::Relaxed)
+    }
+
+    pub fn add_xrun(&self) {
+        self.xruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_overrun(&self) {
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_resync(&self) {
+        self.resyncs.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The buffers a stereo analysis is delivered through
+pub struct StereoDelivery {
+    pub left: Input<SpectrumFrame>,
+    pub right: Input<SpectrumFrame>,
+    pub mid: Input<SpectrumFrame>,
+    pub side: Input<SpectrumFrame>,
+    /// Per bin phase correlation, mapped from [-1,1] to [-90,0] so it can be drawn like a spectrum
+    pub correlation: Input<SpectrumFrame>,
+}
+
+/// Analyzes the streams of one channel until the connection goes away for good
+pub fn process_thread(
+    streams: Receiver<Stream>,
+    mut delivery: Input<SpectrumFrame>,
+    overlap: Overlap,
+    shared_settings: Arc<Mutex<AnalysisSettings>>,
+) {
     thread::spawn(move || {
+        // Nothing to analyze while waiting for the audio server
+        let mut channel = match streams.recv() {
+            Ok(stream) => {
+                let settings =
+                    AnalysisSettings::read(&shared_settings, AnalysisSettings::default());
+                Channel::new(stream, settings, overlap)
+            }
+            Err(_) => return,
+        };
+
         loop {
-            // Loop until the ringbuffer has enough samples
-            if consumer.len() >= BUFFER_SIZE {
-                // TODO: Carry over FFT like wolf
-                // TODO: We always know the max length, so an array would be possible. But that wouldn't easily allow changabe resolutions
-                // TODO: Constant-Q transform
-                // https://en.wikipedia.org/wiki/Constant-Q_transform
-                // TODO: Research for other transforms with more exact low frequencies
-                // Increasing the sample content adds more low frequency data
-                // Maybe 2 ffts, one with every second sample removed to half the sample rate. Thus the lower frequency is pushed up
-
-                // Init the buffer
-                let mut buffer = Vec::<Complex<f32>>::new();
-
-                // And populate it with the samples from the ringbuffer
-                consumer.pop_each(
-                    |e| {
-                        buffer.push(Complex {
-                            re: e / (BUFFER_SIZE as f32 / 2.),
-                            im: 0.0,
-                        });
-                        true
-                    },
-                    Some(BUFFER_SIZE),
-                );
-
-                // Calculate the hann window and multiply it by the signal
-                for (i, val) in buffer.iter_mut().enumerate().take(BUFFER_SIZE) {
-                    *val *= hann_window(i, BUFFER_SIZE);
+            // A restarted server hands over a new stream, possibly at another sample rate
+            match streams.try_recv() {
+                Ok(stream) => channel.reconnect(stream),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return,
+            }
+
+            channel.refresh(AnalysisSettings::read(&shared_settings, channel.settings));
+
+            // Wait until the ringbuffer has enough new samples
+            if channel.ready() {
+                let lagging = channel.lagging();
+                if lagging {
+                    channel.stream.stats.add_resync();
                 }
+                channel.analyze(lagging);
 
-                // Calculate how much 0s have to be padded and do so
-                let padding = FFT_SIZE - BUFFER_SIZE;
+                // Send it to the UI, without waiting for it
+                delivery.write_with(|frame| {
+                    frame.set(
+                        channel.analyzer.frequencies(),
+                        channel.analyzer.magnitudes(),
+                        channel.sample_rate,
+                        channel.settings,
+                    )
+                });
+            } else {
+                // Sleep until the missing samples should have arrived instead of spinning
+                thread::sleep(channel.wait_time());
+            }
+        }
+    });
+}
 
-                for _ in 0..padding {
-                    buffer.push(Complex { re: 0.0, im: 0.0 });
+/// Analyzes the left and right streams together until the connection goes away for good
+///
+/// Besides the spectra of both channels this delivers the mid (L+R) and side (L-R) spectra
+/// and a per bin phase correlation between the channels.
+pub fn stereo_process_thread(
+    streams: Receiver<(Stream, Stream)>,
+    mut delivery: StereoDelivery,
+    overlap: Overlap,
+    shared_settings: Arc<Mutex<AnalysisSettings>>,
+) {
+    thread::spawn(move || {
+        // Nothing to analyze while waiting for the audio server
+        let (mut left, mut right) = match streams.recv() {
+            Ok((left, right)) => {
+                let settings =
+                    AnalysisSettings::read(&shared_settings, AnalysisSettings::default());
+                (
+                    Channel::new(left, settings, overlap),
+                    Channel::new(right, settings, overlap),
+                )
+            }
+            Err(_) => return,
+        };
+
+        let mut stereo = StereoState::new(left.analyzer.frequencies().len());
+
+        loop {
+            // A restarted server hands over new streams, possibly at another sample rate
+            match streams.try_recv() {
+                Ok((new_left, new_right)) => {
+                    left.reconnect(new_left);
+                    right.reconnect(new_right);
                 }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return,
+            }
+
+            // Both channels get the same settings, so their bins always line up
+            let settings = AnalysisSettings::read(&shared_settings, left.settings);
+            let left_changed = left.refresh(settings);
+            let right_changed = right.refresh(settings);
+            if left_changed || right_changed {
+                stereo = StereoState::new(left.analyzer.frequencies().len());
+            }
 
-                // fft set up
-                let mut planner = FftPlanner::<f32>::new();
-
-                let fft = planner.plan_fft_forward(FFT_SIZE);
-
-                fft.process(&mut buffer);
-
-                // Convert the complex signal into magnitudes
-                // Source: http://www.dspguide.com/ch8/8.htm
-                let magnitudes: Vec<f32> = buffer
-                    [0..(buffer.len() as f32 / 2.).floor() as usize + 1]
-                    .iter()
-                    .map(|e: &Complex<f32>| {
-                        let real: f32 = e.re;
-                        let imag: f32 = e.im;
-                        real.pow(2_i8) + imag.pow(2_i8)
-                    })
-                    .map(|e| 10. * (e + 1e-9).log10())
-                    .map(|e| {
-                        if e < -90. {
-                            return -90.;
-                        }
-                        e
-                    })
-                    .collect();
-
-                // Send it to the UI through a mutex
-                if let Ok(mut del) = delivery_mutex.lock() {
-                    *del = magnitudes;
+            // Both channels are fed in the same JACK cycle, so they fill up together
+            if left.ready() && right.ready() {
+                // A DSP that fell behind resyncs both channels together, so they stay aligned
+                let lagging = left.lagging() || right.lagging();
+                if lagging {
+                    left.stream.stats.add_resync();
                 }
+                left.analyze(lagging);
+                right.analyze(lagging);
+
+                stereo.process(left.analyzer.spectrum(), right.analyzer.spectrum());
+
+                let frequencies = left.analyzer.frequencies();
+
+                for (input, magnitudes) in [
+                    (&mut delivery.left, left.analyzer.magnitudes()),
+                    (&mut delivery.right, right.analyzer.magnitudes()),
+                    (&mut delivery.mid, &stereo.mid_db),
+                    (&mut delivery.side, &stereo.side_db),
+                    (&mut delivery.correlation, &stereo.correlation),
+                ] {
+                    input.write_with(|frame| {
+                        frame.set(frequencies, magnitudes, left.sample_rate, left.settings)
+                    });
+                }
+            } else {
+                // Sleep until the missing samples of both channels should have arrived
+                thread::sleep(left.wait_time().max(right.wait_time()));
             }
         }
     });
 }
 
-/// A hann window for the size n
-fn hann_window(i: usize, n: usize) -> f32 {
-    0.5 * (1. - ((2. * std::f32::consts::PI * i as f32) / ((n - 1) as f32)).cos())
+/// A stream together with the analysis that runs on it
+///
+/// The mono and the stereo loop both keep one per stream, so they start over the same way when
+/// the server restarts, changes its rate or the UI changes the analysis.
+struct Channel {
+    stream: Stream,
+    analyzer: Analyzer,
+    window: SlidingWindow,
+    settings: AnalysisSettings,
+    sample_rate: usize,
+    overlap: Overlap,
+    /// A restarted server handed over a new stream since the last refresh
+    reconnected: bool,
+}
+
+impl Channel {
+    fn new(stream: Stream, settings: AnalysisSettings, overlap: Overlap) -> Self {
+        let sample_rate = stream.format.sample_rate();
+        let analyzer = Analyzer::new(&settings, sample_rate);
+        let window = SlidingWindow::for_analyzer(&analyzer, &settings, overlap);
+
+        Channel {
+            stream,
+            analyzer,
+            window,
+            settings,
+            sample_rate,
+            overlap,
+            reconnected: false,
+        }
+    }
+
+    /// Switches to the stream of a restarted server
+    fn reconnect(&mut self, stream: Stream) {
+        self.stream = stream;
+        self.reconnected = true;
+    }
+
+    /// Starts over with an empty frame if the settings, the sample rate or the stream changed,
+    /// returns whether it did
+    fn refresh(&mut self, settings: AnalysisSettings) -> bool {
+        // Samples from before a sample rate change would end up at the wrong frequencies
+        let sample_rate = self.stream.format.sample_rate();
+        if sample_rate != self.sample_rate {
+            let stale = self.stream.consumer.len();
+            self.stream.consumer.discard(stale);
+        }
+
+        if settings == self.settings && sample_rate == self.sample_rate && !self.reconnected {
+            return false;
+        }
+
+        self.settings = settings;
+        self.sample_rate = sample_rate;
+        self.reconnected = false;
+        self.analyzer = Analyzer::new(&settings, sample_rate);
+        self.window = SlidingWindow::for_analyzer(&self.analyzer, &settings, self.overlap);
+        true
+    }
+
+    fn ready(&self) -> bool {
+        self.window.ready(&self.stream.consumer)
+    }
+
+    fn lagging(&self) -> bool {
+        self.window
+            .lagging(&self.stream.consumer, &self.stream.format)
+    }
+
+    fn wait_time(&self) -> Duration {
+        self.window
+            .wait_time(&self.stream.consumer, &self.stream.format)
+    }
+
+    /// Moves the window to the next frame and analyzes it
+    ///
+    /// Stale audio is of no use, so with resync a DSP that fell behind continues with the newest
+    /// samples instead.
+    fn analyze(&mut self, resync: bool) {
+        if resync {
+            self.window.resync(&mut self.stream.consumer);
+        } else {
+            self.window.advance(&mut self.stream.consumer);
+        }
+
+        self.analyzer.process(&self.window.samples);
+    }
+}
+
+/// Mid, side and correlation of a stereo pair, with buffers that are reused every frame
+struct StereoState {
+    // Smoothed auto and cross spectra, a single frame always looks fully correlated
+    left_power: Vec<f32>,
+    right_power: Vec<f32>,
+    cross_power: Vec<Complex<f32>>,
+    mid: Vec<Complex<f32>>,
+    side: Vec<Complex<f32>>,
+    mid_db: Vec<f32>,
+    side_db: Vec<f32>,
+    /// Per bin phase correlation, already mapped to [-90,0]
+    correlation: Vec<f32>,
+}
+
+impl StereoState {
+    fn new(bins: usize) -> Self {
+        StereoState {
+            left_power: vec![0.; bins],
+            right_power: vec![0.; bins],
+            cross_power: vec![Complex { re: 0., im: 0. }; bins],
+            mid: vec![Complex { re: 0., im: 0. }; bins],
+            side: vec![Complex { re: 0., im: 0. }; bins],
+            mid_db: vec![-90.; bins],
+            side_db: vec![-90.; bins],
+            correlation: vec![-45.; bins],
+        }
+    }
+
+    fn process(&mut self, left: &[Complex<f32>], right: &[Complex<f32>]) {
+        for i in 0..self.mid.len() {
+            self.mid[i] = (left[i] + right[i]) * 0.5;
+            self.side[i] = (left[i] - right[i]) * 0.5;
+
+            self.left_power[i] = self.left_power[i] * CORRELATION_SMOOTHING
+                + left[i].norm_sqr() * (1. - CORRELATION_SMOOTHING);
+            self.right_power[i] = self.right_power[i] * CORRELATION_SMOOTHING
+                + right[i].norm_sqr() * (1. - CORRELATION_SMOOTHING);
+            self.cross_power[i] = self.cross_power[i] * CORRELATION_SMOOTHING
+                + left[i] * right[i].conj() * (1. - CORRELATION_SMOOTHING);
+
+            // Re(Sxy) / sqrt(Sxx * Syy) is 1 for in phase, 0 for uncorrelated and -1 for out of phase content
+            let norm = (self.left_power[i] * self.right_power[i]).sqrt();
+            let correlation = if norm < 1e-12 {
+                0.
+            } else {
+                (self.cross_power[i].re / norm).clamp(-1., 1.)
+            };
+            self.correlation[i] = (correlation - 1.) * 45.;
+        }
+
+        to_db(&self.mid, &mut self.mid_db);
+        to_db(&self.side, &mut self.side_db);
+    }
+}
+
+/// Converts a complex spectrum into dB magnitudes, written into the magnitudes buffer
+///
+/// Source: http://www.dspguide.com/ch8/8.htm
+fn to_db(buffer: &[Complex<f32>], magnitudes: &mut Vec<f32>) {
+    magnitudes.clear();
+    magnitudes.extend(
+        buffer
+            .iter()
+            .map(|e: &Complex<f32>| {
+                let real: f32 = e.re;
+                let imag: f32 = e.im;
+                real.pow(2_i8) + imag.pow(2_i8)
+            })
+            .map(|e| 10. * (e + 1e-9).log10())
+            .map(|e| {
+                if e < -90. {
+                    return -90.;
+                }
+                e
+            }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use ringbuf::RingBuffer;
+
+    use super::{SlidingWindow, StreamFormat};
+
+    #[test]
+    fn lags_with_frames_longer_than_the_ringbuffer() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(1000).split();
+        let format = StreamFormat::new(48000, 64);
+        let mut window = SlidingWindow::new(4000, 500);
+        for (idx, sample) in window.samples.iter_mut().enumerate() {
+            *sample = idx as f32;
+        }
+
+        producer.push_iter(&mut (0..900).map(|idx| 10000. + idx as f32));
+        assert!(!window.lagging(&consumer, &format));
+
+        // Another period wouldn't fit anymore
+        producer.push_iter(&mut (900..990).map(|idx| 10000. + idx as f32));
+        assert!(window.lagging(&consumer, &format));
+
+        window.resync(&mut consumer);
+        assert!(consumer.is_empty());
+
+        // The oldest samples move to the front, the new ones follow in order
+        let (old, new) = window.samples.split_at(4000 - 990);
+        assert!(old
+            .iter()
+            .zip(990..)
+            .all(|(sample, idx)| *sample == idx as f32));
+        assert!(new
+            .iter()
+            .zip(0..)
+            .all(|(sample, idx)| *sample == 10000. + idx as f32));
+    }
+
+    #[test]
+    fn resync_keeps_the_newest_samples() {
+        let (mut producer, mut consumer) = RingBuffer::<f32>::new(50_000).split();
+        let format = StreamFormat::new(48000, 256);
+        let mut window = SlidingWindow::new(1024, 256);
+
+        producer.push_iter(&mut (0..1024 + 256 + 256).map(|idx| idx as f32));
+        assert!(!window.lagging(&consumer, &format));
+
+        producer.push(1536.).unwrap();
+        assert!(window.lagging(&consumer, &format));
+
+        window.resync(&mut consumer);
+        assert!(consumer.is_empty());
+        assert!(window
+            .samples
+            .iter()
+            .zip(513..)
+            .all(|(sample, idx)| *sample == idx as f32));
+    }
+
+    #[test]
+    fn offline_streams_never_lag() {
+        let (mut producer, consumer) = RingBuffer::<f32>::new(1000).split();
+        let format = StreamFormat::offline(48000, 64);
+        let window = SlidingWindow::new(4000, 500);
+
+        producer.push_iter(&mut (0..1000).map(|idx| idx as f32));
+        assert!(!window.lagging(&consumer, &format));
+    }
 }
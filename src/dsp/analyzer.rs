@@ -0,0 +1,97 @@
+use rustfft::num_complex::Complex;
+
+use super::cqt::ConstantQ;
+use super::fft::ZeroPaddedFft;
+use super::multires::MultiResolution;
+use super::{to_db, AnalysisSettings, Backend};
+
+/// Analyzes frames of one channel with the backend from the settings
+///
+/// Owns everything a frame needs, the plans, window tables and output buffers are
+/// only allocated when the analyzer is created and reused for every frame after that.
+pub struct Analyzer {
+    transform: Transform,
+    frequencies: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    magnitudes: Vec<f32>,
+}
+
+enum Transform {
+    Fft(ZeroPaddedFft),
+    ConstantQ(ConstantQ),
+    MultiResolution(MultiResolution),
+}
+
+impl Analyzer {
+    pub fn new(settings: &AnalysisSettings, sample_rate: usize) -> Self {
+        let (transform, frequencies) = match settings.backend {
+            Backend::Fft => {
+                let fft =
+                    ZeroPaddedFft::new(settings.window, settings.window_size, settings.fft_size());
+                let frequencies = (0..fft.bins())
+                    .map(|i| i as f32 * sample_rate as f32 / fft.fft_size() as f32)
+                    .collect();
+                (Transform::Fft(fft), frequencies)
+            }
+            Backend::ConstantQ(bins_per_octave) => {
+                let cqt = ConstantQ::new(bins_per_octave, settings.window, sample_rate);
+                let frequencies = cqt.frequencies().to_vec();
+                (Transform::ConstantQ(cqt), frequencies)
+            }
+            Backend::MultiResolution => {
+                let multires = MultiResolution::new(
+                    settings.window_size,
+                    settings.padding,
+                    settings.window,
+                    sample_rate,
+                );
+                let frequencies = multires.frequencies().to_vec();
+                (Transform::MultiResolution(multires), frequencies)
+            }
+        };
+
+        let bins = frequencies.len();
+
+        Analyzer {
+            transform,
+            frequencies,
+            spectrum: vec![Complex { re: 0.0, im: 0.0 }; bins],
+            magnitudes: vec![-90.; bins],
+        }
+    }
+
+    /// The amount of samples a frame has to hold
+    pub fn frame_size(&self) -> usize {
+        match &self.transform {
+            Transform::Fft(fft) => fft.window_size(),
+            Transform::ConstantQ(cqt) => cqt.frame_size(),
+            Transform::MultiResolution(multires) => multires.frame_size(),
+        }
+    }
+
+    /// The center frequencies of the bins
+    pub fn frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    /// Analyzes one frame with the newest sample at the end
+    pub fn process(&mut self, samples: &[f32]) {
+        match &mut self.transform {
+            Transform::Fft(fft) => fft.process(samples, &mut self.spectrum),
+            Transform::ConstantQ(cqt) => cqt.process(samples, &mut self.spectrum),
+            Transform::MultiResolution(multires) => multires.process(samples, &mut self.spectrum),
+        }
+
+        to_db(&self.spectrum, &mut self.magnitudes);
+    }
+
+    /// The complex spectrum of the last frame
+    pub fn spectrum(&self) -> &[Complex<f32>] {
+        &self.spectrum
+    }
+
+    /// The dB magnitudes of the last frame
+    pub fn magnitudes(&self) -> &[f32] {
+        &self.magnitudes
+    }
+}
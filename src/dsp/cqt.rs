@@ -0,0 +1,178 @@
+use std::sync::Arc;
+
+use realfft::{RealFftPlanner, RealToComplex};
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use super::WindowFunction;
+
+/// Lowest frequency the constant-Q transform analyzes
+const MIN_FREQUENCY: f32 = 20.;
+
+/// Longest kernel in samples, the lowest bins get a lower Q instead of growing without bounds
+const MAX_KERNEL_LENGTH: usize = 32768;
+
+/// Spectral kernel values below this fraction of the kernel's peak get dropped, about -80dB
+const SPARSITY: f32 = 0.0001;
+
+/// A constant-Q transform with log spaced bins
+///
+/// Every bin has its own kernel, a windowed complex sinusoid that is Q periods of its frequency long.
+/// So the low bins look far into the past while the high bins stay responsive.
+/// All kernels end at the newest sample of the frame.
+///
+/// Instead of correlating the samples with every kernel, the frame goes through one FFT and
+/// every bin only sums the few FFT bins its kernel's spectrum has energy in. The kernels of
+/// windows with high sidelobes don't get sparse, those are applied to the samples directly.
+///
+/// Source: https://en.wikipedia.org/wiki/Constant-Q_transform
+/// Source: Brown and Puckette, An efficient algorithm for the calculation of a constant Q transform
+pub struct ConstantQ {
+    frequencies: Vec<f32>,
+    kernels: Vec<Kernel>,
+    fft: Arc<dyn RealToComplex<f32>>,
+    buffer: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+enum Kernel {
+    /// The samples of the kernel, aligned to the end of the frame
+    Temporal(Vec<Complex<f32>>),
+    /// The FFT bins of the kernel's spectrum that matter, with their index into the full spectrum
+    Spectral(Vec<(usize, Complex<f32>)>),
+}
+
+impl ConstantQ {
+    pub fn new(bins_per_octave: usize, window: WindowFunction, sample_rate: usize) -> Self {
+        let q = 1. / (2_f32.powf(1. / bins_per_octave as f32) - 1.);
+        let nyquist = sample_rate as f32 / 2.;
+
+        let frequencies: Vec<f32> = (0..)
+            .map(|k| MIN_FREQUENCY * 2_f32.powf(k as f32 / bins_per_octave as f32))
+            .take_while(|freq| *freq < nyquist)
+            .collect();
+
+        let temporal_kernels: Vec<Vec<Complex<f32>>> = frequencies
+            .iter()
+            .map(|freq| {
+                let length =
+                    ((q * sample_rate as f32 / freq).ceil() as usize).min(MAX_KERNEL_LENGTH);
+
+                // The window table already compensates the gain, so a full scale sine reads as 0dB
+                window
+                    .table(length)
+                    .iter()
+                    .enumerate()
+                    .map(|(n, w)| {
+                        let phase =
+                            -2. * std::f32::consts::PI * freq * n as f32 / sample_rate as f32;
+                        Complex::from_polar(*w, phase)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let fft_size = temporal_kernels
+            .iter()
+            .map(|kernel| kernel.len())
+            .max()
+            .unwrap_or(1)
+            .next_power_of_two();
+
+        let kernel_fft = FftPlanner::<f32>::new().plan_fft_forward(fft_size);
+        let kernels = temporal_kernels
+            .into_iter()
+            .map(|kernel| Kernel::new(kernel, kernel_fft.as_ref()))
+            .collect();
+
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(fft_size);
+        let buffer = fft.make_input_vec();
+        let spectrum = fft.make_output_vec();
+        let scratch = fft.make_scratch_vec();
+
+        ConstantQ {
+            frequencies,
+            kernels,
+            fft,
+            buffer,
+            spectrum,
+            scratch,
+        }
+    }
+
+    /// The amount of samples a frame holds, the longest kernel rounded up to the FFT size
+    pub fn frame_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    pub fn frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    /// Applies every kernel to the newest samples of the frame and writes the bins into output
+    pub fn process(&mut self, samples: &[f32], output: &mut [Complex<f32>]) {
+        let fft_size = self.buffer.len();
+        self.buffer
+            .copy_from_slice(&samples[samples.len() - fft_size..]);
+
+        // Only fails if the buffer sizes don't match the plan, which would be a bug in new
+        self.fft
+            .process_with_scratch(&mut self.buffer, &mut self.spectrum, &mut self.scratch)
+            .expect("buffers are made by the plan");
+
+        let spectrum = &self.spectrum;
+
+        for (val, kernel) in output.iter_mut().zip(self.kernels.iter()) {
+            *val = match kernel {
+                Kernel::Temporal(kernel) => {
+                    let start = samples.len() - kernel.len();
+                    samples[start..]
+                        .iter()
+                        .zip(kernel.iter())
+                        .map(|(sample, k)| k * sample)
+                        .sum()
+                }
+                Kernel::Spectral(kernel) => kernel
+                    .iter()
+                    .map(|(m, k)| {
+                        // The spectrum of real samples is symmetric, only the lower half is computed
+                        let bin = if *m < spectrum.len() {
+                            spectrum[*m].conj()
+                        } else {
+                            spectrum[fft_size - m]
+                        };
+                        bin * k
+                    })
+                    .sum(),
+            };
+        }
+    }
+}
+
+impl Kernel {
+    /// Turns the kernel into a sparse spectral one, unless that takes more work per frame
+    ///
+    /// By Parseval, the sum of the samples times the kernel equals the sum over the conjugated
+    /// spectrum of the samples times the spectrum of the kernel, divided by the FFT size.
+    fn new(temporal: Vec<Complex<f32>>, fft: &dyn Fft<f32>) -> Self {
+        let fft_size = fft.len();
+        let mut spectrum = vec![Complex { re: 0.0, im: 0.0 }; fft_size];
+        spectrum[fft_size - temporal.len()..].copy_from_slice(&temporal);
+        fft.process(&mut spectrum);
+
+        let peak = spectrum.iter().map(|k| k.norm()).fold(0., f32::max);
+        let sparse: Vec<(usize, Complex<f32>)> = spectrum
+            .iter()
+            .enumerate()
+            .filter(|(_, k)| k.norm() >= peak * SPARSITY)
+            .map(|(m, k)| (m, k / fft_size as f32))
+            .collect();
+
+        if sparse.len() < temporal.len() {
+            Kernel::Spectral(sparse)
+        } else {
+            Kernel::Temporal(temporal)
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+use realfft::{RealFftPlanner, RealToComplex};
+use rustfft::num_complex::Complex;
+
+use super::WindowFunction;
+
+/// A windowed, zero padded FFT that keeps its plan and buffers between frames
+///
+/// The samples are real, so a real-to-complex FFT computes only the bins up to nyquist,
+/// which takes about half the work of a complex FFT of the same size.
+pub struct ZeroPaddedFft {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window_table: Vec<f32>,
+    buffer: Vec<f32>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl ZeroPaddedFft {
+    pub fn new(window: WindowFunction, window_size: usize, fft_size: usize) -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let fft = planner.plan_fft_forward(fft_size);
+
+        let buffer = fft.make_input_vec();
+        let scratch = fft.make_scratch_vec();
+
+        ZeroPaddedFft {
+            fft,
+            window_table: window.table(window_size),
+            buffer,
+            scratch,
+        }
+    }
+
+    /// Amount of samples one frame holds before padding
+    pub fn window_size(&self) -> usize {
+        self.window_table.len()
+    }
+
+    pub fn fft_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Amount of bins up to and including nyquist
+    pub fn bins(&self) -> usize {
+        self.fft_size() / 2 + 1
+    }
+
+    /// Windows and pads the samples and writes their spectrum up to nyquist into output,
+    /// which has to hold exactly `bins()` values
+    pub fn process(&mut self, samples: &[f32], output: &mut [Complex<f32>]) {
+        let window_size = self.window_size();
+
+        for ((val, e), w) in self
+            .buffer
+            .iter_mut()
+            .zip(samples.iter())
+            .zip(self.window_table.iter())
+        {
+            *val = e * w;
+        }
+
+        // The padding has been overwritten by the last FFT
+        for val in self.buffer[window_size..].iter_mut() {
+            *val = 0.0;
+        }
+
+        // Only fails if the buffer sizes don't match the plan, which would be a bug in the caller
+        self.fft
+            .process_with_scratch(&mut self.buffer, output, &mut self.scratch)
+            .expect("output has to hold fft_size / 2 + 1 bins");
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Instant;
+
+use super::AnalysisSettings;
+
+/// One analyzed spectrum together with everything needed to draw it
+///
+/// The UI only draws what is in here, so it doesn't have to know which
+/// transform, FFT size or sample rate produced it.
+#[derive(Clone, PartialEq)]
+pub struct SpectrumFrame {
+    /// Center frequencies of the bins in Hz
+    pub frequencies: Vec<f32>,
+    /// Magnitudes of the bins in dB
+    pub magnitudes: Vec<f32>,
+    pub sample_rate: usize,
+    /// When the frame was analyzed
+    pub timestamp: Instant,
+    /// The parameters the frame was analyzed with
+    pub settings: AnalysisSettings,
+}
+
+impl SpectrumFrame {
+    pub fn new(
+        frequencies: Vec<f32>,
+        magnitudes: Vec<f32>,
+        sample_rate: usize,
+        settings: AnalysisSettings,
+    ) -> Self {
+        SpectrumFrame {
+            frequencies,
+            magnitudes,
+            sample_rate,
+            timestamp: Instant::now(),
+            settings,
+        }
+    }
+
+    /// A frame without any bins, for before the first analysis is done
+    pub fn empty(sample_rate: usize) -> Self {
+        SpectrumFrame::new(
+            Vec::new(),
+            Vec::new(),
+            sample_rate,
+            AnalysisSettings::default(),
+        )
+    }
+
+    /// Overwrites the frame with a new analysis, reusing the allocated vectors
+    pub fn set(
+        &mut self,
+        frequencies: &[f32],
+        magnitudes: &[f32],
+        sample_rate: usize,
+        settings: AnalysisSettings,
+    ) {
+        self.frequencies.clear();
+        self.frequencies.extend_from_slice(frequencies);
+        self.magnitudes.clear();
+        self.magnitudes.extend_from_slice(magnitudes);
+        self.sample_rate = sample_rate;
+        self.timestamp = Instant::now();
+        self.settings = settings;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.magnitudes.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.magnitudes.len()
+    }
+}
@@ -0,0 +1,232 @@
+use rustfft::num_complex::Complex;
+
+use super::fft::ZeroPaddedFft;
+use super::WindowFunction;
+
+/// Decimation factors of the stages, from the full rate for the highs down to the lows
+const DECIMATIONS: &[usize] = &[1, 4, 16];
+
+/// The crossover into the next higher stage starts at this fraction of the crossover frequency
+const CROSSOVER_START: f32 = 0.75;
+
+/// Taps of the decimation filter per decimation factor
+const TAPS_PER_DECIMATION: usize = 16;
+
+/// Several FFTs at decimated sample rates, stitched into one spectrum
+///
+/// Every stage analyzes the same amount of samples after decimation, so a stage with
+/// decimation D looks D times further into the past and has D times finer bins.
+/// Each stage covers the frequencies up to a quarter of its sample rate, which leaves
+/// the decimation filter room for its transition band. Below each crossover frequency
+/// the magnitudes are faded over to the next stage, the phase is kept from the lower one.
+pub struct MultiResolution {
+    stages: Vec<Stage>,
+    outputs: Vec<Point>,
+    frequencies: Vec<f32>,
+    frame_size: usize,
+}
+
+/// One FFT on a decimated version of the frame
+struct Stage {
+    decimation: usize,
+    /// Lowpass that is applied before dropping samples, empty for the full rate stage
+    filter: Vec<f32>,
+    fft: ZeroPaddedFft,
+    decimated: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+/// Where an output point takes its value from
+struct Point {
+    stage: usize,
+    bin: usize,
+    /// Stage, fractional bin and weight of the higher stage inside a crossover region
+    blend: Option<(usize, f32, f32)>,
+}
+
+impl MultiResolution {
+    pub fn new(
+        window_size: usize,
+        padding: usize,
+        window: WindowFunction,
+        sample_rate: usize,
+    ) -> Self {
+        let fft_size = window_size * padding;
+
+        let stages: Vec<Stage> = DECIMATIONS
+            .iter()
+            .map(|decimation| {
+                let fft = ZeroPaddedFft::new(window, window_size, fft_size);
+                let bins = fft.bins();
+
+                Stage {
+                    decimation: *decimation,
+                    filter: lowpass(*decimation),
+                    fft,
+                    decimated: vec![0.; window_size],
+                    spectrum: vec![Complex { re: 0.0, im: 0.0 }; bins],
+                }
+            })
+            .collect();
+
+        // Go from the lowest frequencies in the most decimated stage up to the full rate stage
+        let mut outputs = Vec::new();
+        let mut frequencies = Vec::new();
+        let mut lower_limit = -1.;
+
+        for (idx, stage) in stages.iter().enumerate().rev() {
+            let bin_width = stage.bin_width(sample_rate);
+
+            // The full rate stage goes up to nyquist
+            let upper_limit = if idx == 0 {
+                sample_rate as f32 / 2.
+            } else {
+                sample_rate as f32 / (4. * stage.decimation as f32)
+            };
+
+            for bin in 0..fft_size / 2 + 1 {
+                let frequency = bin as f32 * bin_width;
+
+                if frequency <= lower_limit || frequency > upper_limit {
+                    continue;
+                }
+
+                // Fade towards the next higher stage right below the crossover
+                let blend = if idx > 0 && frequency > upper_limit * CROSSOVER_START {
+                    let weight = (frequency - upper_limit * CROSSOVER_START)
+                        / (upper_limit * (1. - CROSSOVER_START));
+                    let position = frequency / stages[idx - 1].bin_width(sample_rate);
+                    Some((idx - 1, position, weight))
+                } else {
+                    None
+                };
+
+                outputs.push(Point {
+                    stage: idx,
+                    bin,
+                    blend,
+                });
+                frequencies.push(frequency);
+            }
+
+            // The next stage continues right above the crossover
+            lower_limit = upper_limit;
+        }
+
+        let frame_size = stages
+            .iter()
+            .map(|stage| stage.frame_size(window_size))
+            .max()
+            .unwrap_or(window_size);
+
+        MultiResolution {
+            stages,
+            outputs,
+            frequencies,
+            frame_size,
+        }
+    }
+
+    /// The amount of samples the most decimated stage needs
+    pub fn frame_size(&self) -> usize {
+        self.frame_size
+    }
+
+    pub fn frequencies(&self) -> &[f32] {
+        &self.frequencies
+    }
+
+    /// Analyzes every stage and writes the stitched spectrum into output
+    pub fn process(&mut self, samples: &[f32], output: &mut [Complex<f32>]) {
+        for stage in self.stages.iter_mut() {
+            stage.process(samples);
+        }
+
+        for (val, point) in output.iter_mut().zip(self.outputs.iter()) {
+            let value = self.stages[point.stage].spectrum[point.bin];
+
+            *val = match point.blend {
+                None => value,
+                Some((stage, position, weight)) => {
+                    let other = interpolate_magnitude(&self.stages[stage].spectrum, position);
+                    let magnitude = value.norm() * (1. - weight) + other * weight;
+
+                    if value.norm() > 1e-12 {
+                        value * (magnitude / value.norm())
+                    } else {
+                        Complex::new(magnitude, 0.)
+                    }
+                }
+            };
+        }
+    }
+}
+
+impl Stage {
+    /// Frequency distance between two bins of this stage
+    fn bin_width(&self, sample_rate: usize) -> f32 {
+        sample_rate as f32 / (self.decimation * self.fft.fft_size()) as f32
+    }
+
+    /// The amount of samples that are needed for window_size decimated samples
+    fn frame_size(&self, window_size: usize) -> usize {
+        (window_size - 1) * self.decimation + self.filter.len().max(1)
+    }
+
+    /// Filters and decimates the newest samples of the frame and analyzes them
+    fn process(&mut self, samples: &[f32]) {
+        let start = samples.len() - self.frame_size(self.decimated.len());
+
+        if self.filter.is_empty() {
+            self.decimated.copy_from_slice(&samples[start..]);
+        } else {
+            for (m, val) in self.decimated.iter_mut().enumerate() {
+                let offset = start + m * self.decimation;
+                *val = self
+                    .filter
+                    .iter()
+                    .zip(samples[offset..].iter())
+                    .map(|(h, x)| h * x)
+                    .sum();
+            }
+        }
+
+        self.fft.process(&self.decimated, &mut self.spectrum);
+    }
+}
+
+/// A windowed sinc lowpass with unity gain, cutting off a bit below the decimated nyquist
+fn lowpass(decimation: usize) -> Vec<f32> {
+    if decimation == 1 {
+        return Vec::new();
+    }
+
+    let taps = TAPS_PER_DECIMATION * decimation + 1;
+    let cutoff = 0.9 / (2. * decimation as f32);
+    let center = (taps - 1) as f32 / 2.;
+
+    let filter: Vec<f32> = (0..taps)
+        .map(|i| {
+            let x = i as f32 - center;
+            let sinc = if x == 0. {
+                2. * cutoff
+            } else {
+                (2. * std::f32::consts::PI * cutoff * x).sin() / (std::f32::consts::PI * x)
+            };
+            sinc * WindowFunction::Blackman.value(i, taps)
+        })
+        .collect();
+
+    let gain: f32 = filter.iter().sum();
+
+    filter.iter().map(|h| h / gain).collect()
+}
+
+/// Linearly interpolates the magnitude of a spectrum at a fractional bin
+fn interpolate_magnitude(spectrum: &[Complex<f32>], position: f32) -> f32 {
+    let idx = (position.floor() as usize).min(spectrum.len() - 1);
+    let next = (idx + 1).min(spectrum.len() - 1);
+    let fraction = position - idx as f32;
+
+    spectrum[idx].norm() * (1. - fraction) + spectrum[next].norm() * fraction
+}
@@ -0,0 +1,170 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Set in the shared index when the back buffer holds a value the reader hasn't seen yet
+const NEW_DATA: usize = 0b100;
+const INDEX_MASK: usize = 0b011;
+
+/// Creates a lock free single producer single consumer triple buffer
+///
+/// The writer always has a buffer to write into and the reader always has a buffer to read from,
+/// the third one is swapped between them through a single atomic. So neither side ever waits
+/// for the other, the reader just gets the newest value that was completely written.
+pub fn triple_buffer<T: Clone + Send>(initial: T) -> (Input<T>, Output<T>) {
+    let shared = Arc::new(Shared {
+        buffers: [
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial.clone()),
+            UnsafeCell::new(initial),
+        ],
+        back: AtomicUsize::new(1),
+    });
+
+    (
+        Input {
+            shared: shared.clone(),
+            write_idx: 0,
+        },
+        Output {
+            shared,
+            read_idx: 2,
+        },
+    )
+}
+
+struct Shared<T> {
+    buffers: [UnsafeCell<T>; 3],
+    /// Index of the back buffer, together with the NEW_DATA flag
+    back: AtomicUsize,
+}
+
+// The input and the output only ever touch the buffer they own, ownership is handed over through the atomic
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+/// The writing half of a triple buffer
+pub struct Input<T> {
+    shared: Arc<Shared<T>>,
+    write_idx: usize,
+}
+
+impl<T> Input<T> {
+    /// Updates the buffer of the input in place and publishes it, replacing the value the reader
+    /// hasn't picked up yet if there is one
+    ///
+    /// The buffer still holds an older value, so its allocations can be reused.
+    pub fn write_with(&mut self, f: impl FnOnce(&mut T)) {
+        // Safety: write_idx is owned by the input until it is swapped into the back
+        unsafe {
+            f(&mut *self.shared.buffers[self.write_idx].get());
+        }
+
+        let old_back = self
+            .shared
+            .back
+            .swap(self.write_idx | NEW_DATA, Ordering::AcqRel);
+        self.write_idx = old_back & INDEX_MASK;
+    }
+}
+
+/// The reading half of a triple buffer
+pub struct Output<T> {
+    shared: Arc<Shared<T>>,
+    read_idx: usize,
+}
+
+impl<T> Output<T> {
+    /// Whether a value was written since the last read
+    pub fn updated(&self) -> bool {
+        self.shared.back.load(Ordering::Acquire) & NEW_DATA != 0
+    }
+
+    /// Returns the newest value, or None if nothing was written since the last read
+    pub fn read(&mut self) -> Option<&T> {
+        if !self.updated() {
+            return None;
+        }
+
+        let old_back = self.shared.back.swap(self.read_idx, Ordering::AcqRel);
+        self.read_idx = old_back & INDEX_MASK;
+
+        // Safety: read_idx is owned by the output until it is swapped into the back
+        Some(unsafe { &*self.shared.buffers[self.read_idx].get() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::triple_buffer;
+
+    #[test]
+    fn write_then_read() {
+        let (mut input, mut output) = triple_buffer(0);
+
+        input.write_with(|buffer| *buffer = 1);
+        assert_eq!(output.read(), Some(&1));
+
+        input.write_with(|buffer| *buffer = 2);
+        input.write_with(|buffer| *buffer = 3);
+        assert_eq!(output.read(), Some(&3));
+    }
+
+    #[test]
+    fn read_without_write() {
+        let (mut input, mut output) = triple_buffer(0);
+
+        // The initial value was never written
+        assert!(!output.updated());
+        assert_eq!(output.read(), None);
+
+        input.write_with(|buffer| *buffer = 1);
+        assert!(output.updated());
+        assert_eq!(output.read(), Some(&1));
+
+        assert!(!output.updated());
+        assert_eq!(output.read(), None);
+    }
+
+    #[test]
+    fn write_with_reuses_buffers() {
+        let (mut input, mut output) = triple_buffer(vec![0; 4]);
+
+        input.write_with(|buffer| buffer.fill(1));
+        assert_eq!(output.read(), Some(&vec![1; 4]));
+
+        input.write_with(|buffer| buffer.push(2));
+        assert_eq!(output.read(), Some(&vec![0, 0, 0, 0, 2]));
+    }
+
+    #[test]
+    fn concurrent_reads_are_whole_and_newer() {
+        const FRAMES: usize = 100_000;
+        const FRAME_SIZE: usize = 64;
+
+        let (mut input, mut output) = triple_buffer(vec![0; FRAME_SIZE]);
+
+        let writer = thread::spawn(move || {
+            for frame in 1..=FRAMES {
+                input.write_with(|buffer| buffer.fill(frame));
+            }
+        });
+
+        let mut last = 0;
+        while last < FRAMES {
+            if let Some(buffer) = output.read() {
+                let frame = buffer[0];
+                assert!(
+                    buffer.iter().all(|value| *value == frame),
+                    "torn frame {:?}",
+                    buffer
+                );
+                assert!(frame > last, "frame {} after frame {}", frame, last);
+                last = frame;
+            }
+        }
+
+        writer.join().unwrap();
+    }
+}
@@ -0,0 +1,131 @@
+use std::f32::consts::PI;
+
+/// The window functions a frame can be multiplied with before the FFT
+#[derive(Clone, Copy, PartialEq)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    BlackmanHarris,
+    Nuttall,
+    FlatTop,
+    /// Kaiser window with the shape parameter beta
+    Kaiser(f32),
+}
+
+impl WindowFunction {
+    /// Amount of selectable window functions
+    pub const COUNT: usize = 8;
+
+    /// The window at position idx in the selection, Kaiser uses the given beta
+    pub fn from_index(idx: usize, beta: f32) -> Self {
+        match idx {
+            0 => WindowFunction::Rectangular,
+            1 => WindowFunction::Hann,
+            2 => WindowFunction::Hamming,
+            3 => WindowFunction::Blackman,
+            4 => WindowFunction::BlackmanHarris,
+            5 => WindowFunction::Nuttall,
+            6 => WindowFunction::FlatTop,
+            _ => WindowFunction::Kaiser(beta),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WindowFunction::Rectangular => "Rectangular",
+            WindowFunction::Hann => "Hann",
+            WindowFunction::Hamming => "Hamming",
+            WindowFunction::Blackman => "Blackman",
+            WindowFunction::BlackmanHarris => "Blackman-Harris",
+            WindowFunction::Nuttall => "Nuttall",
+            WindowFunction::FlatTop => "Flat top",
+            WindowFunction::Kaiser(_) => "Kaiser",
+        }
+    }
+
+    /// The window at sample i of n samples
+    pub fn value(&self, i: usize, n: usize) -> f32 {
+        match self {
+            WindowFunction::Rectangular => 1.,
+            WindowFunction::Hann => cosine_sum(&[0.5, 0.5], i, n),
+            WindowFunction::Hamming => cosine_sum(&[0.54, 0.46], i, n),
+            WindowFunction::Blackman => cosine_sum(&[0.42, 0.5, 0.08], i, n),
+            WindowFunction::BlackmanHarris => {
+                cosine_sum(&[0.35875, 0.48829, 0.14128, 0.01168], i, n)
+            }
+            WindowFunction::Nuttall => cosine_sum(&[0.355768, 0.487396, 0.144232, 0.012604], i, n),
+            WindowFunction::FlatTop => cosine_sum(
+                &[0.21557895, 0.41663158, 0.27726316, 0.083578947, 0.006947368],
+                i,
+                n,
+            ),
+            WindowFunction::Kaiser(beta) => {
+                let x = 2. * i as f32 / (n - 1) as f32 - 1.;
+                bessel_i0(beta * (1. - x * x).sqrt()) / bessel_i0(*beta)
+            }
+        }
+    }
+
+    /// Equivalent noise bandwidth of the window for n samples, in bins of an n point FFT
+    ///
+    /// The power of a sine spreads over the bins around it, summing them up gives this many
+    /// times the power the peak bin shows.
+    pub fn enbw(&self, n: usize) -> f32 {
+        let (sum, square_sum) = (0..n)
+            .map(|i| self.value(i, n))
+            .fold((0., 0.), |(sum, square_sum), w| {
+                (sum + w, square_sum + w * w)
+            });
+
+        n as f32 * square_sum / (sum * sum)
+    }
+
+    /// Precomputes the window for n samples, compensated by its coherent gain
+    ///
+    /// The coefficients include the FFT normalization, so a full scale sine reads as 0dB
+    /// independent of the window and its length.
+    pub fn table(&self, n: usize) -> Vec<f32> {
+        let window: Vec<f32> = (0..n).map(|i| self.value(i, n)).collect();
+
+        // A sine with amplitude A peaks at A/2 * sum(w) in the spectrum
+        let gain = window.iter().sum::<f32>() / 2.;
+
+        window.iter().map(|w| w / gain).collect()
+    }
+}
+
+/// A generalized cosine window with the coefficients a_0..a_k
+///
+/// Source: https://en.wikipedia.org/wiki/Window_function#Cosine-sum_windows
+fn cosine_sum(coefficients: &[f32], i: usize, n: usize) -> f32 {
+    coefficients
+        .iter()
+        .enumerate()
+        .map(|(k, a)| {
+            let sign = if k % 2 == 0 { 1. } else { -1. };
+            sign * a * ((2. * PI * k as f32 * i as f32) / ((n - 1) as f32)).cos()
+        })
+        .sum()
+}
+
+/// The zeroth order modified bessel function of the first kind, needed for the kaiser window
+///
+/// Evaluated through its power series, which converges quickly for the betas used here
+fn bessel_i0(x: f32) -> f32 {
+    let mut sum = 1.;
+    let mut term = 1.;
+    let half_x = x / 2.;
+
+    for k in 1..50 {
+        term *= (half_x / k as f32) * (half_x / k as f32);
+        sum += term;
+
+        if term < sum * 1e-8 {
+            break;
+        }
+    }
+
+    sum
+}
@@ -0,0 +1,268 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ringbuf::Producer;
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::DecoderOptions;
+use symphonia::core::errors::Error;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::audio::{capture_period, new_streams, AudioBackend, Ports, Session, Status};
+use crate::dsp::{StreamFormat, StreamStats};
+
+/// Name of the backend, shown in the UI while the file gets decoded
+const NAME: &str = "File";
+
+/// Amount of samples per channel that get handed to the DSP at once
+const PERIOD_SIZE: usize = 1024;
+
+/// How long to wait for commands while paused or for room in the ringbuffers
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// How long to wait for the DSP to make room when playing as fast as possible
+const FAST_WAIT: Duration = Duration::from_millis(1);
+
+/// How often the position in the UI gets updated
+const STATUS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Controls the playback of a file from the UI
+pub enum TransportCommand {
+    TogglePause,
+    /// Jumps to the given position, 0 is the start and 1 the end of the file
+    Seek(f32),
+}
+
+/// A whole file, decoded to one buffer of samples per channel
+struct Recording {
+    channels: Vec<Vec<f32>>,
+    sample_rate: usize,
+}
+
+impl Recording {
+    /// Length in samples per channel
+    fn len(&self) -> usize {
+        self.channels.first().map_or(0, Vec::len)
+    }
+
+    /// The file channel that feeds the given analyzer channel, wrapping around if there are fewer
+    fn channel(&self, channel: usize) -> &[f32] {
+        &self.channels[channel % self.channels.len()]
+    }
+}
+
+/// Plays a WAV, FLAC or AIFF file into the analysis instead of listening to an audio server
+///
+/// The file gets decoded up front, so seeking is instant. It plays at its own sample rate,
+/// either in real time or as fast as the DSP can take it. In the latter case no samples get
+/// skipped, every frame of the file gets analyzed.
+pub struct FileBackend {
+    path: PathBuf,
+    fast: bool,
+    transport: Receiver<TransportCommand>,
+}
+
+impl FileBackend {
+    pub fn new(path: PathBuf, fast: bool, transport: Receiver<TransportCommand>) -> Self {
+        FileBackend {
+            path,
+            fast,
+            transport,
+        }
+    }
+}
+
+impl AudioBackend for FileBackend {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn start(self: Box<Self>, channels: Vec<String>, session: Session) {
+        thread::spawn(move || {
+            session.set_status(Status::Waiting(NAME));
+            session.set_ports(Ports::default());
+
+            match decode(&self.path) {
+                Ok(recording) => playback(&recording, channels.len(), *self, session),
+                Err(e) => eprintln!("Failed to read {}: {}", self.path.display(), e),
+            }
+        });
+    }
+}
+
+/// Feeds the recording into the streams until the UI goes away
+fn playback(recording: &Recording, channels: usize, backend: FileBackend, mut session: Session) {
+    let format = Arc::new(if backend.fast {
+        StreamFormat::offline(recording.sample_rate, PERIOD_SIZE)
+    } else {
+        StreamFormat::new(recording.sample_rate, PERIOD_SIZE)
+    });
+    let stats = Arc::new(StreamStats::default());
+
+    let period_duration =
+        Duration::from_secs_f64(PERIOD_SIZE as f64 / recording.sample_rate as f64);
+    let duration = recording.len() as f32 / recording.sample_rate as f32;
+
+    let mut producers = start_streams(channels, &format, &stats, &mut session);
+    let mut position = 0;
+    let mut paused = false;
+    let mut next_period = Instant::now();
+    let mut last_status = Instant::now() - STATUS_INTERVAL;
+
+    loop {
+        loop {
+            match backend.transport.try_recv() {
+                Ok(TransportCommand::TogglePause) => {
+                    paused = !paused;
+                    // Pressing play at the end starts over
+                    if !paused && position >= recording.len() {
+                        position = 0;
+                        producers = start_streams(channels, &format, &stats, &mut session);
+                    }
+                    next_period = Instant::now();
+                }
+                Ok(TransportCommand::Seek(x)) => {
+                    position = (x.clamp(0., 1.) * recording.len() as f32) as usize;
+                    // New streams make the DSP drop the samples from before the jump
+                    producers = start_streams(channels, &format, &stats, &mut session);
+                    next_period = Instant::now();
+                }
+                Err(_) => break,
+            }
+        }
+
+        // A file has no ports to connect
+        while session.commands.try_recv().is_ok() {}
+
+        if position >= recording.len() {
+            paused = true;
+        }
+
+        if last_status.elapsed() >= STATUS_INTERVAL {
+            last_status = Instant::now();
+            session.set_status(Status::Playback {
+                sample_rate: recording.sample_rate,
+                position: position as f32 / recording.sample_rate as f32,
+                duration,
+                paused,
+            });
+        }
+
+        if paused {
+            thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        let end = (position + PERIOD_SIZE).min(recording.len());
+
+        if backend.fast {
+            // Waiting for room instead of dropping samples, the DSP sets the pace
+            if producers
+                .iter()
+                .any(|producer| producer.remaining() < end - position)
+            {
+                thread::sleep(FAST_WAIT);
+                continue;
+            }
+        } else {
+            thread::sleep(next_period.saturating_duration_since(Instant::now()));
+            // After falling far behind, e.g. on a busy system, continue from now instead of catching up
+            next_period = next_period.max(Instant::now() - period_duration) + period_duration;
+        }
+
+        let periods = (0..channels).map(|channel| &recording.channel(channel)[position..end]);
+        capture_period(periods, &mut producers, &stats);
+        position = end;
+    }
+}
+
+/// Hands new streams to the DSP and returns the producers that feed them
+fn start_streams(
+    channels: usize,
+    format: &Arc<StreamFormat>,
+    stats: &Arc<StreamStats>,
+    session: &mut Session,
+) -> Vec<Producer<f32>> {
+    let (producers, streams) = new_streams(channels, format, stats);
+    (session.on_connect)(streams);
+    producers
+}
+
+/// Decodes the whole file into planar samples
+fn decode(path: &Path) -> Result<Recording, Error> {
+    let mut hint = Hint::new();
+    if let Some(extension) = path.extension().and_then(|extension| extension.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    let source = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut reader = symphonia::default::get_probe()
+        .format(
+            &hint,
+            source,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )?
+        .format;
+
+    let track = reader
+        .default_track()
+        .ok_or(Error::Unsupported("no audio track"))?;
+    let track_id = track.id;
+    let sample_rate = track
+        .codec_params
+        .sample_rate
+        .ok_or(Error::Unsupported("unknown sample rate"))? as usize;
+    let mut decoder =
+        symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default())?;
+
+    let mut channels: Vec<Vec<f32>> = Vec::new();
+
+    loop {
+        let packet = match reader.next_packet() {
+            Ok(packet) => packet,
+            // Running out of packets is how the end of the file shows up
+            Err(Error::IoError(e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // A corrupt packet only costs its own samples
+            Err(Error::DecodeError(_)) => continue,
+            Err(e) => return Err(e),
+        };
+
+        let spec = *decoded.spec();
+        let mut buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        buffer.copy_interleaved_ref(decoded);
+
+        let channel_count = spec.channels.count();
+        channels.resize_with(channel_count, Vec::new);
+        for frame in buffer.samples().chunks_exact(channel_count) {
+            for (channel, sample) in channels.iter_mut().zip(frame) {
+                channel.push(*sample);
+            }
+        }
+    }
+
+    if channels.is_empty() {
+        return Err(Error::Unsupported("no samples"));
+    }
+
+    Ok(Recording {
+        channels,
+        sample_rate,
+    })
+}
@@ -1,29 +1,449 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use jack::{
+    AsyncClient, AudioIn, Client, ClientOptions, ClientStatus, Control, Frames, Port, PortFlags,
+    PortId, PortSpec, ProcessScope,
+};
 use ringbuf::Producer;
-use jack::{self, AsyncClient, Client, ClosureProcessHandler, Control, ProcessScope};
 
-pub fn start_jack(mut producer: Producer<f32>) -> AsyncClient<(), ClosureProcessHandler<fn(&Client, &ProcessScope) -> Control>> {
-    let (client, _status) =
-    jack::Client::new("jack_fourier", jack::ClientOptions::NO_START_SERVER).unwrap();
+use crate::audio::{
+    capture_period, new_streams, AudioBackend, PortCommand, PortPattern, Ports, Session, Status,
+};
+use crate::dsp::{Stream, StreamFormat, StreamStats};
+
+/// Name of the backend, shown in the UI
+const NAME: &str = "JACK";
+
+/// How long to wait before trying to reach the JACK server again
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a running connection is checked for shutdowns and format changes
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Copies the samples of every port into the ringbuffer of its channel
+struct Capture {
+    ports: Vec<Port<AudioIn>>,
+    producers: Vec<Producer<f32>>,
+    format: Arc<StreamFormat>,
+    stats: Arc<StreamStats>,
+}
+
+impl jack::ProcessHandler for Capture {
+    fn process(&mut self, _: &Client, ps: &ProcessScope) -> Control {
+        let periods = self.ports.iter().map(|in_port| in_port.as_slice(ps));
+        capture_period(periods, &mut self.producers, &self.stats);
+
+        // Continue as normal
+        Control::Continue
+    }
+
+    fn buffer_size(&mut self, _: &Client, size: Frames) -> Control {
+        self.format.set_buffer_size(size as usize);
+        Control::Continue
+    }
+}
+
+/// Notices when the server goes away, so the connection thread can start over, and
+/// passes sample rate changes on to the DSP
+struct Notifications {
+    shutdown: Arc<AtomicBool>,
+    /// Ports came or went, new ones may need to be connected
+    ports_changed: Arc<AtomicBool>,
+    /// Ports or connections changed, so the port picker is outdated
+    graph_changed: Arc<AtomicBool>,
+    /// Sources that were auto-connected while they exist, see new_sources
+    known_sources: Arc<Mutex<Vec<String>>>,
+    format: Arc<StreamFormat>,
+    stats: Arc<StreamStats>,
+}
+
+impl jack::NotificationHandler for Notifications {
+    fn shutdown(&mut self, _status: ClientStatus, _reason: &str) {
+        // Runs like a signal handler, so only set a flag here
+        self.shutdown.store(true, Ordering::Release);
+    }
+
+    fn sample_rate(&mut self, _: &Client, srate: Frames) -> Control {
+        self.format.set_sample_rate(srate as usize);
+        Control::Continue
+    }
+
+    fn xrun(&mut self, _: &Client) -> Control {
+        self.stats.add_xrun();
+        Control::Continue
+    }
+
+    fn port_registration(&mut self, client: &Client, port_id: PortId, is_registered: bool) {
+        // A source that goes away gets connected again when it comes back, even if it comes
+        // back before the connection thread looks at the ports again
+        if !is_registered {
+            let name = client.port_by_id(port_id).and_then(|port| port.name().ok());
+            if let (Some(name), Ok(mut known)) = (name, self.known_sources.lock()) {
+                known.retain(|source| *source != name);
+            }
+        }
+
+        // Connecting from a notification isn't allowed, the connection thread does that
+        self.ports_changed.store(true, Ordering::Release);
+        self.graph_changed.store(true, Ordering::Release);
+    }
+
+    fn ports_connected(
+        &mut self,
+        _: &Client,
+        _port_id_a: PortId,
+        _port_id_b: PortId,
+        _are_connected: bool,
+    ) {
+        self.graph_changed.store(true, Ordering::Release);
+    }
+}
+
+/// Captures from the ports of a JACK server
+///
+/// Waits for the server if it isn't running yet and reconnects after it restarted. Every
+/// connection registers the ports again and hands out a new stream per port. Source ports
+/// matching one of the patterns get connected to the inputs, also when they only show up
+/// later. The ports of the server are published for the port picker, which sends back the
+/// connections the user asks for.
+pub struct JackBackend {
+    sources: Vec<PortPattern>,
+}
+
+impl JackBackend {
+    pub fn new(sources: Vec<PortPattern>) -> Self {
+        JackBackend { sources }
+    }
+}
+
+impl AudioBackend for JackBackend {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn start(self: Box<Self>, channels: Vec<String>, session: Session) {
+        connection_thread(channels, self.sources, session);
+    }
+}
+
+/// Keeps the analyzer connected to JACK in the background
+fn connection_thread(port_names: Vec<String>, sources: Vec<PortPattern>, mut session: Session) {
+    thread::spawn(move || loop {
+        session.set_status(Status::Waiting(NAME));
+        session.set_ports(Ports::default());
+
+        // Whatever the user picked before the server went away refers to old ports
+        while session.commands.try_recv().is_ok() {}
+
+        match connect(&port_names) {
+            Ok(Connection {
+                client,
+                streams,
+                format,
+                stats,
+                inputs,
+                shutdown,
+                ports_changed,
+                graph_changed,
+                known_sources,
+            }) => {
+                (session.on_connect)(streams);
+
+                while !shutdown.load(Ordering::Acquire) {
+                    let client = client.as_client();
+
+                    if ports_changed.swap(false, Ordering::AcqRel) {
+                        connect_sources(client, &sources, &inputs, &known_sources);
+                    }
+
+                    while let Ok(command) = session.commands.try_recv() {
+                        run_command(client, command, &inputs);
+                    }
+
+                    if graph_changed.swap(false, Ordering::AcqRel) {
+                        session.set_ports(list_ports(client, &inputs));
+                    }
+
+                    // The server may change the sample rate or the buffer size at any time
+                    session.set_status(Status::Connected {
+                        backend: NAME,
+                        sample_rate: format.sample_rate(),
+                        buffer_size: format.buffer_size(),
+                        xruns: stats.xruns(),
+                        overruns: stats.overruns(),
+                        resyncs: stats.resyncs(),
+                    });
+
+                    thread::sleep(POLL_INTERVAL);
+                }
+
+                // The server is gone, all that's left to do is to close the client
+                drop(client);
+            }
+            Err(_) => thread::sleep(RETRY_INTERVAL),
+        }
+    });
+}
+
+/// An active client and the streams it captures into
+struct Connection {
+    client: AsyncClient<Notifications, Capture>,
+    streams: Vec<Stream>,
+    format: Arc<StreamFormat>,
+    stats: Arc<StreamStats>,
+    /// Full names of the registered input ports
+    inputs: Vec<String>,
+    shutdown: Arc<AtomicBool>,
+    ports_changed: Arc<AtomicBool>,
+    graph_changed: Arc<AtomicBool>,
+    known_sources: Arc<Mutex<Vec<String>>>,
+}
+
+/// Opens a client with a new ringbuffer per port and starts capturing
+fn connect(port_names: &[String]) -> Result<Connection, jack::Error> {
+    let (client, _status) = Client::new("jack_fourier", ClientOptions::NO_START_SERVER)?;
 
-    let in_port = client
-        .register_port("fourier_in", jack::AudioIn::default())
-        .unwrap();
+    let ports = port_names
+        .iter()
+        .map(|name| client.register_port(name, AudioIn::default()))
+        .collect::<Result<Vec<_>, _>>()?;
+    let inputs = ports
+        .iter()
+        .map(|port| port.name())
+        .collect::<Result<Vec<_>, _>>()?;
 
-    let process = jack::ClosureProcessHandler::new(
-        move |_: &jack::Client, ps: &jack::ProcessScope| -> jack::Control {
-            // Get output buffer
-            let in_p = in_port.as_slice(ps);
+    let format = Arc::new(StreamFormat::new(
+        client.sample_rate(),
+        client.buffer_size() as usize,
+    ));
 
-            // Write output
-            for input_l in in_p {
-                // Check if the current volume is at the destination by checking if there's steps left
-                producer.push(*input_l);
+    let stats = Arc::new(StreamStats::default());
+
+    let (producers, streams) = new_streams(ports.len(), &format, &stats);
+
+    let shutdown = Arc::new(AtomicBool::new(false));
+    // Sources that already exist get connected and listed right away
+    let ports_changed = Arc::new(AtomicBool::new(true));
+    let graph_changed = Arc::new(AtomicBool::new(true));
+    // Sources are only connected when they show up, so the user can disconnect them
+    let known_sources = Arc::new(Mutex::new(Vec::new()));
+
+    let notifications = Notifications {
+        shutdown: shutdown.clone(),
+        ports_changed: ports_changed.clone(),
+        graph_changed: graph_changed.clone(),
+        known_sources: known_sources.clone(),
+        format: format.clone(),
+        stats: stats.clone(),
+    };
+    let capture = Capture {
+        ports,
+        producers,
+        format: format.clone(),
+        stats: stats.clone(),
+    };
+
+    let client = client.activate_async(notifications, capture)?;
+
+    Ok(Connection {
+        client,
+        streams,
+        format,
+        stats,
+        inputs,
+        shutdown,
+        ports_changed,
+        graph_changed,
+        known_sources,
+    })
+}
+
+/// Connects the audio outputs that match the patterns and aren't known yet to the inputs
+fn connect_sources(
+    client: &Client,
+    patterns: &[PortPattern],
+    inputs: &[String],
+    known: &Mutex<Vec<String>>,
+) {
+    let outputs = client.ports(
+        None,
+        Some(AudioIn::default().jack_port_type()),
+        PortFlags::IS_OUTPUT,
+    );
+
+    // Not held while connecting, the notifications need it when ports go away
+    let sources = match known.lock() {
+        Ok(mut known) => new_sources(patterns, &outputs, inputs.len(), &mut known),
+        Err(_) => return,
+    };
+
+    for (source, input) in sources {
+        let input = &inputs[input];
+
+        match client.connect_ports_by_name(&source, input) {
+            Ok(()) | Err(jack::Error::PortAlreadyConnected(_, _)) => {}
+            Err(_) => {
+                eprintln!("Failed to connect {} to {}.", source, input);
+
+                // Most likely gone again already, so it gets another try when it comes back
+                if let Ok(mut known) = known.lock() {
+                    known.retain(|known| *known != source);
+                }
             }
+        }
+    }
+}
+
+/// The outputs that match the patterns and aren't in known yet, with the input each one feeds
+///
+/// The sources are taken in the order of the patterns and distributed over the inputs,
+/// so `system:capture_.*` feeds capture_1 into the first input, capture_2 into the second
+/// and so on. With more sources than inputs they wrap around and get mixed by JACK.
+///
+/// The returned sources are added to known, so a source the user disconnected stays
+/// disconnected. Known sources that are no longer among the outputs are forgotten, so they
+/// get connected again when they come back.
+fn new_sources(
+    patterns: &[PortPattern],
+    outputs: &[String],
+    inputs: usize,
+    known: &mut Vec<String>,
+) -> Vec<(String, usize)> {
+    known.retain(|source| outputs.contains(source));
+
+    if inputs == 0 {
+        return Vec::new();
+    }
 
-            // Continue as normal
-            jack::Control::Continue
+    let mut sources: Vec<&String> = Vec::new();
+    for pattern in patterns {
+        for output in outputs.iter().filter(|output| pattern.matches(output)) {
+            if !sources.contains(&output) {
+                sources.push(output);
+            }
+        }
+    }
+
+    let mut new = Vec::new();
+    for (idx, source) in sources.into_iter().enumerate() {
+        if !known.contains(source) {
+            known.push(source.clone());
+            new.push((source.clone(), idx % inputs));
         }
+    }
+    new
+}
+
+/// The audio outputs of the server and the current connections of the inputs
+fn list_ports(client: &Client, inputs: &[String]) -> Ports {
+    let sources = client.ports(
+        None,
+        Some(AudioIn::default().jack_port_type()),
+        PortFlags::IS_OUTPUT,
     );
 
-    client.activate_async((), process).unwrap()
-}
\ No newline at end of file
+    let connections = inputs
+        .iter()
+        .map(|input| match client.port_by_name(input) {
+            Some(port) => sources
+                .iter()
+                .filter(|source| port.is_connected_to(source).unwrap_or(false))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        })
+        .collect();
+
+    Ports {
+        inputs: inputs.to_vec(),
+        sources,
+        connections,
+    }
+}
+
+fn run_command(client: &Client, command: PortCommand, inputs: &[String]) {
+    match command {
+        PortCommand::Connect { source, input } => {
+            if let Some(input) = inputs.get(input) {
+                match client.connect_ports_by_name(&source, input) {
+                    Ok(()) | Err(jack::Error::PortAlreadyConnected(_, _)) => {}
+                    Err(_) => eprintln!("Failed to connect {} to {}.", source, input),
+                }
+            }
+        }
+        PortCommand::Disconnect { source, input } => {
+            if let Some(input) = inputs.get(input) {
+                if client.disconnect_ports_by_name(&source, input).is_err() {
+                    eprintln!("Failed to disconnect {} from {}.", source, input);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::new_sources;
+    use crate::audio::PortPattern;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn sources_are_distributed_over_the_inputs() {
+        let patterns = [PortPattern::new("system:capture_.*")];
+        let outputs = names(&[
+            "system:capture_1",
+            "other:out",
+            "system:capture_2",
+            "system:capture_3",
+        ]);
+        let mut known = Vec::new();
+
+        assert_eq!(
+            new_sources(&patterns, &outputs, 2, &mut known),
+            vec![
+                ("system:capture_1".to_string(), 0),
+                ("system:capture_2".to_string(), 1),
+                ("system:capture_3".to_string(), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn known_sources_are_not_connected_again() {
+        let patterns = [PortPattern::new("a:out_.*")];
+        let mut outputs = names(&["a:out_1", "a:out_2"]);
+        let mut known = Vec::new();
+
+        assert_eq!(new_sources(&patterns, &outputs, 2, &mut known).len(), 2);
+        // The user disconnected a:out_1 meanwhile, that has to stick
+        assert!(new_sources(&patterns, &outputs, 2, &mut known).is_empty());
+
+        // A new source keeps the input of its place among the matches
+        outputs.push("a:out_3".to_string());
+        assert_eq!(
+            new_sources(&patterns, &outputs, 2, &mut known),
+            vec![("a:out_3".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn sources_that_come_back_are_connected_again() {
+        let patterns = [PortPattern::new("ardour:master/audio_out 1")];
+        let outputs = names(&["ardour:master/audio_out 1"]);
+        let mut known = Vec::new();
+
+        assert_eq!(new_sources(&patterns, &outputs, 1, &mut known).len(), 1);
+
+        // Gone by the time of the next look, so it is forgotten
+        assert!(new_sources(&patterns, &[], 1, &mut known).is_empty());
+        assert!(known.is_empty());
+
+        assert_eq!(new_sources(&patterns, &outputs, 1, &mut known).len(), 1);
+    }
+}
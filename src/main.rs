@@ -1,47 +1,158 @@
+use std::sync::mpsc;
 use std::sync::{Arc, Mutex};
 
-use ringbuf::RingBuffer;
-
+#[cfg(feature = "alsa")]
+mod alsa;
+mod audio;
+mod config;
 mod dsp;
+mod file;
+mod jack;
+#[cfg(feature = "pipewire")]
+mod pipewire;
 mod ui;
 
+/// Default amount of samples per analysis frame, can be changed in the UI
 pub const BUFFER_SIZE: usize = 1024;
+/// Default FFT size after zero padding, can be changed in the UI
 pub const FFT_SIZE: usize = 4096;
 
 fn main() {
-    let jack_dsp_rb = RingBuffer::<f32>::new(50_000);
-    let (mut jack_dsp_prod, jack_dsp_cons) = jack_dsp_rb.split();
+    let config = config::Config::from_args();
 
-    let (client, _status) =
-        jack::Client::new("jack_fourier", jack::ClientOptions::NO_START_SERVER).unwrap();
+    let port_names: Vec<String> = if config.stereo {
+        vec!["fourier_in_l".to_string(), "fourier_in_r".to_string()]
+    } else {
+        (1..=config.channels)
+            .map(|n| format!("fourier_in_{}", n))
+            .collect()
+    };
 
-    let in_port = client
-        .register_port("fourier_in", jack::AudioIn::default())
-        .unwrap();
+    let channels = port_names.len();
 
-    let process = jack::ClosureProcessHandler::new(
-        move |_: &jack::Client, ps: &jack::ProcessScope| -> jack::Control {
-            // Get output buffer
-            let in_p = in_port.as_slice(ps);
+    // Only a file can be paused and seeked, so only a file gets the transport controls
+    let mut transport = None;
+    let backend: Box<dyn audio::AudioBackend> = match config.file {
+        Some(path) => {
+            let (transport_sender, transport_receiver) = mpsc::channel();
+            transport = Some(transport_sender);
+            Box::new(file::FileBackend::new(
+                path,
+                config.fast,
+                transport_receiver,
+            ))
+        }
+        None => match config.driver {
+            config::Driver::Jack => Box::new(jack::JackBackend::new(config.sources)),
+            #[cfg(feature = "alsa")]
+            config::Driver::Alsa => Box::new(alsa::AlsaBackend::new(config.device)),
+            #[cfg(feature = "pipewire")]
+            config::Driver::PipeWire => Box::new(pipewire::PipeWireBackend::new(config.device)),
+        },
+    };
 
-            // Write output
-            for input_l in in_p {
-                // Check if the current volume is at the destination by checking if there's steps left
-                jack_dsp_prod.push(*input_l).unwrap_or(());
-            }
+    // Until the server is reachable the views assume a common sample rate, frames carry the real one
+    let sr = audio::DEFAULT_SAMPLE_RATE;
+    let status = Arc::new(Mutex::new(audio::Status::Waiting(backend.name())));
+    let ports = Arc::new(Mutex::new(audio::Ports::default()));
+    let (port_command_sender, port_command_receiver) = mpsc::channel();
 
-            // Continue as normal
-            jack::Control::Continue
-        },
-    );
+    // Hands the streams of every connection to the DSP threads
+    let start = |on_connect: Box<dyn FnMut(Vec<dsp::Stream>) + Send>| {
+        backend.start(
+            port_names,
+            audio::Session {
+                status: status.clone(),
+                ports: ports.clone(),
+                commands: port_command_receiver,
+                on_connect,
+            },
+        )
+    };
+
+    let new_delivery = || dsp::triple_buffer::triple_buffer(dsp::SpectrumFrame::empty(sr));
+
+    let settings = Arc::new(Mutex::new(dsp::AnalysisSettings::default()));
+
+    if config.stereo {
+        let (stream_sender, stream_receiver) = mpsc::channel();
 
-    let sr = client.sample_rate();
+        // Both channels of a connection go to the DSP together
+        start(Box::new(move |mut streams| {
+            let right = streams.pop().unwrap();
+            let left = streams.pop().unwrap();
+            stream_sender.send((left, right)).unwrap_or(());
+        }));
+
+        let (left_in, left_out) = new_delivery();
+        let (right_in, right_out) = new_delivery();
+        let (mid_in, mid_out) = new_delivery();
+        let (side_in, side_out) = new_delivery();
+        let (correlation_in, correlation_out) = new_delivery();
+
+        let delivery = dsp::StereoDelivery {
+            left: left_in,
+            right: right_in,
+            mid: mid_in,
+            side: side_in,
+            correlation: correlation_in,
+        };
+
+        let traces = vec![
+            ui::Trace::new("L", left_out),
+            ui::Trace::new("R", right_out),
+            ui::Trace::new("Mid", mid_out),
+            ui::Trace::new("Side", side_out),
+        ];
+
+        dsp::stereo_process_thread(stream_receiver, delivery, config.overlap, settings.clone());
+
+        ui::ui(
+            traces,
+            Some(correlation_out),
+            settings,
+            ui::BackendLink {
+                status,
+                ports,
+                port_commands: port_command_sender,
+                transport,
+            },
+            sr,
+        );
+    } else {
+        let (stream_senders, stream_receivers): (Vec<_>, Vec<_>) =
+            (0..channels).map(|_| mpsc::channel()).unzip();
+
+        // Every port has its own DSP thread
+        start(Box::new(move |streams| {
+            for (stream_sender, stream) in stream_senders.iter().zip(streams) {
+                stream_sender.send(stream).unwrap_or(());
+            }
+        }));
 
-    let _jack_client = client.activate_async((), process).unwrap();
+        let traces: Vec<ui::Trace> = stream_receivers
+            .into_iter()
+            .enumerate()
+            .map(|(i, stream_receiver)| {
+                let (dsp_ui_in, dsp_ui_out) = new_delivery();
 
-    let dsp_ui_mutex = Arc::new(Mutex::new(vec![-90.; 1024]));
+                dsp::process_thread(stream_receiver, dsp_ui_in, config.overlap, settings.clone());
 
-    dsp::process_thread(jack_dsp_cons, dsp_ui_mutex.clone());
+                ui::Trace::new(&(i + 1).to_string(), dsp_ui_out)
+            })
+            .collect();
 
-    ui::ui(dsp_ui_mutex, sr);
+        ui::ui(
+            traces,
+            None,
+            settings,
+            ui::BackendLink {
+                status,
+                ports,
+                port_commands: port_command_sender,
+                transport,
+            },
+            sr,
+        );
+    }
 }
@@ -0,0 +1,219 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use pipewire as pw;
+use pw::properties::properties;
+use pw::spa;
+use pw::stream::StreamState;
+use ringbuf::Producer;
+use spa::param::audio::{AudioFormat, AudioInfoRaw};
+use spa::param::format::{MediaSubtype, MediaType};
+use spa::param::format_utils;
+use spa::pod::Pod;
+
+use crate::audio::{
+    capture_interleaved, new_streams, AudioBackend, Ports, Session, Status, DEFAULT_SAMPLE_RATE,
+};
+use crate::dsp::{StreamFormat, StreamStats};
+
+/// Name of the backend, shown in the UI
+const NAME: &str = "PipeWire";
+
+/// How long to wait before trying to reach the PipeWire daemon again
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the main loop runs between status updates
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Size of one sample in the F32LE format that gets negotiated
+const SAMPLE_SIZE: usize = std::mem::size_of::<f32>();
+
+/// State of the process callback, which runs in the real-time thread of PipeWire
+struct Capture {
+    producers: Vec<Producer<f32>>,
+    format: Arc<StreamFormat>,
+    stats: Arc<StreamStats>,
+}
+
+/// Captures from the PipeWire graph with one stream that has a channel per analyzer input
+///
+/// Without a target the session manager links the stream to the default source, a target
+/// node can be given by its name or serial. The session manager also decides about the
+/// links afterwards, so the port picker stays empty.
+pub struct PipeWireBackend {
+    target: Option<String>,
+}
+
+impl PipeWireBackend {
+    pub fn new(target: Option<String>) -> Self {
+        PipeWireBackend { target }
+    }
+}
+
+impl AudioBackend for PipeWireBackend {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn start(self: Box<Self>, channels: Vec<String>, mut session: Session) {
+        thread::spawn(move || {
+            pw::init();
+
+            loop {
+                session.set_status(Status::Waiting(NAME));
+                session.set_ports(Ports::default());
+
+                if let Err(e) = capture(self.target.as_deref(), channels.len(), &mut session) {
+                    eprintln!("PipeWire capture stopped: {}", e);
+                }
+                thread::sleep(RETRY_INTERVAL);
+            }
+        });
+    }
+}
+
+/// Connects a capture stream and runs the main loop until the daemon or the stream fail
+fn capture(target: Option<&str>, channels: usize, session: &mut Session) -> Result<(), pw::Error> {
+    let mainloop = pw::main_loop::MainLoop::new(None)?;
+    let context = pw::context::Context::new(&mainloop)?;
+    let core = context.connect(None)?;
+
+    // Set from the callbacks, checked between the iterations of the main loop
+    let failed = Arc::new(AtomicBool::new(false));
+
+    let core_failed = failed.clone();
+    let _core_listener = core
+        .add_listener_local()
+        .error(move |_, _, _, message| {
+            eprintln!("PipeWire error: {}", message);
+            core_failed.store(true, Ordering::Release);
+        })
+        .register();
+
+    let mut props = properties! {
+        *pw::keys::MEDIA_TYPE => "Audio",
+        *pw::keys::MEDIA_CATEGORY => "Capture",
+        *pw::keys::MEDIA_ROLE => "Music",
+    };
+    if let Some(target) = target {
+        props.insert(*pw::keys::TARGET_OBJECT, target);
+    }
+
+    let stream = pw::stream::Stream::new(&core, "jack_fourier", props)?;
+
+    // The graph may run at another rate, the stream reports it once the format is negotiated
+    let format = Arc::new(StreamFormat::new(DEFAULT_SAMPLE_RATE, 0));
+    let stats = Arc::new(StreamStats::default());
+    let (producers, streams) = new_streams(channels, &format, &stats);
+
+    let capture = Capture {
+        producers,
+        format: format.clone(),
+        stats: stats.clone(),
+    };
+
+    let stream_failed = failed.clone();
+    let _stream_listener = stream
+        .add_local_listener_with_user_data(capture)
+        .state_changed(move |_, _, _, state| {
+            if let StreamState::Error(_) | StreamState::Unconnected = state {
+                stream_failed.store(true, Ordering::Release);
+            }
+        })
+        .param_changed(|_, capture, id, param| {
+            // No param means the format got cleared
+            let Some(param) = param else {
+                return;
+            };
+            if id != spa::param::ParamType::Format.as_raw() {
+                return;
+            }
+
+            match format_utils::parse_format(param) {
+                Ok((MediaType::Audio, MediaSubtype::Raw)) => {}
+                _ => return,
+            }
+
+            let mut info = AudioInfoRaw::new();
+            if info.parse(param).is_ok() {
+                capture.format.set_sample_rate(info.rate() as usize);
+            }
+        })
+        .process(|stream, capture| {
+            let Some(mut buffer) = stream.dequeue_buffer() else {
+                return;
+            };
+
+            let Some(data) = buffer.datas_mut().first_mut() else {
+                return;
+            };
+            let offset = data.chunk().offset() as usize;
+            let size = data.chunk().size() as usize;
+
+            if let Some(bytes) = data.data() {
+                let Some(bytes) = bytes.get(offset..offset + size) else {
+                    return;
+                };
+
+                let channels = capture.producers.len();
+                capture
+                    .format
+                    .set_buffer_size(size / SAMPLE_SIZE / channels);
+
+                let samples = bytes
+                    .chunks_exact(SAMPLE_SIZE)
+                    .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]]));
+                capture_interleaved(samples, &mut capture.producers, &capture.stats);
+            }
+        })
+        .register()?;
+
+    // Interleaved floats with a channel per input, the rate is left to the graph
+    let mut audio_info = AudioInfoRaw::new();
+    audio_info.set_format(AudioFormat::F32LE);
+    audio_info.set_channels(channels as u32);
+    let object = spa::pod::Object {
+        type_: spa::utils::SpaTypes::ObjectParamFormat.as_raw(),
+        id: spa::param::ParamType::EnumFormat.as_raw(),
+        properties: audio_info.into(),
+    };
+    let values: Vec<u8> = spa::pod::serialize::PodSerializer::serialize(
+        std::io::Cursor::new(Vec::new()),
+        &spa::pod::Value::Object(object),
+    )
+    .map_err(|_| pw::Error::CreationFailed)?
+    .0
+    .into_inner();
+    let mut params = [Pod::from_bytes(&values).ok_or(pw::Error::CreationFailed)?];
+
+    stream.connect(
+        spa::utils::Direction::Input,
+        None,
+        pw::stream::StreamFlags::AUTOCONNECT
+            | pw::stream::StreamFlags::MAP_BUFFERS
+            | pw::stream::StreamFlags::RT_PROCESS,
+        &mut params,
+    )?;
+
+    (session.on_connect)(streams);
+
+    while !failed.load(Ordering::Acquire) {
+        mainloop.loop_().iterate(POLL_INTERVAL);
+
+        // Nothing sends port commands to PipeWire, so they are just dropped
+        while session.commands.try_recv().is_ok() {}
+
+        session.set_status(Status::Connected {
+            backend: NAME,
+            sample_rate: format.sample_rate(),
+            buffer_size: format.buffer_size(),
+            xruns: stats.xruns(),
+            overruns: stats.overruns(),
+            resyncs: stats.resyncs(),
+        });
+    }
+
+    Ok(())
+}
@@ -1,17 +1,35 @@
+use std::cell::RefCell;
+use std::sync::mpsc::Sender;
 use std::sync::{Arc, Mutex};
 
+use crate::audio::{PortCommand, Ports, Status};
+use crate::dsp::triple_buffer::Output;
+use crate::dsp::{AnalysisSettings, Backend, SpectrumFrame, WindowFunction};
+use crate::file::TransportCommand;
+
 use crate::ui::{
-    frequency_markers::FrequencyMarkers, spectrometer::Spectrometer, volume_markers::VolumeMarkers,
+    frequency_markers::FrequencyMarkers, spectrogram::Spectrogram, spectrometer::Spectrometer,
+    volume_markers::VolumeMarkers,
 };
 use vizia::prelude::*;
 
 use self::{
+    bands::BANDS_PER_OCTAVE,
+    colormap::Colormap,
+    controls::{controls, Section},
     frequency_markers::FreqMarkerHandle,
-    spectrometer::{Scale, SpectrometerHandle, Style},
+    port_picker::port_picker,
+    spectrogram::{Scroll, SpectrogramHandle},
+    spectrometer::{Fill, Scale, SpectrometerHandle, Style},
 };
 
+mod bands;
 pub(crate) mod bin;
+mod colormap;
+mod controls;
 mod frequency_markers;
+mod port_picker;
+mod spectrogram;
 mod spectrometer;
 mod volume_markers;
 
@@ -42,22 +60,227 @@ const STYLE: &str = r#"
     }
 "#;
 
+/// Trace colors of the channels as RGB, repeated if there are more channels than colors
+const CHANNEL_COLORS: &[(u8, u8, u8)] = &[
+    (245, 78, 71),
+    (71, 184, 245),
+    (139, 245, 71),
+    (245, 213, 71),
+    (200, 71, 245),
+    (71, 245, 194),
+    (245, 154, 71),
+    (245, 71, 168),
+];
+
+/// A named spectrum that gets drawn as its own trace
+pub struct Trace {
+    name: String,
+    delivery: Output<SpectrumFrame>,
+}
+
+impl Trace {
+    pub fn new(name: &str, delivery: Output<SpectrumFrame>) -> Self {
+        Trace {
+            name: name.to_string(),
+            delivery,
+        }
+    }
+}
+
+/// What the UI shares with the audio backend
+pub struct BackendLink {
+    pub status: Arc<Mutex<Status>>,
+    pub ports: Arc<Mutex<Ports>>,
+    pub port_commands: Sender<PortCommand>,
+    /// Only set when playing a file
+    pub transport: Option<Sender<TransportCommand>>,
+}
+
+/// Resolutions the constant-Q knob can select
+const BINS_PER_OCTAVE: &[usize] = &[12, 24, 36, 48];
+
+/// Range of the spectrogram history knob in rows
+const MIN_HISTORY: usize = 50;
+const MAX_HISTORY: usize = 1000;
+/// Range of the spectrogram speed knob in rows per second
+const MIN_ROWS_PER_SECOND: f32 = 5.;
+const MAX_ROWS_PER_SECOND: f32 = 60.;
+
+/// Range of the floor knob in dB, the level that gets the quietest color
+const MIN_FLOOR: f32 = -120.;
+const MAX_FLOOR: f32 = -30.;
+
+/// Range of the line width knob in pixels
+const MIN_LINE_WIDTH: f32 = 0.5;
+const MAX_LINE_WIDTH: f32 = 6.;
+
+/// Largest beta the kaiser knob can select
+const MAX_KAISER_BETA: f32 = 20.;
+const DEFAULT_KAISER_BETA: f32 = 8.6;
+
 #[derive(Lens)]
 pub struct UIData {
-    data: Vec<f32>,
+    data: Vec<SpectrumFrame>,
+    visible: Vec<bool>,
+    correlation: SpectrumFrame,
     attack: f32,
     release: f32,
     sr: usize,
     min_freq: f32,
     max_freq: f32,
     slope: f32,
+    window_size: f32,
+    padding: f32,
+    window_function: f32,
+    kaiser_beta: f32,
+    backend: f32,
+    bins_per_octave: f32,
+    settings: Arc<Mutex<AnalysisSettings>>,
+    status: String,
+    ports: Ports,
+    show_ports: bool,
+    port_commands: Sender<PortCommand>,
+    /// Playback position in the file, 0 is the start and 1 the end
+    position: f32,
+    paused: bool,
+    transport: Option<Sender<TransportCommand>>,
+    show_spectrogram: bool,
+    /// The spectrogram scrolls to the left with the frequencies going up, instead of down
+    scroll_sideways: bool,
+    history: f32,
+    speed: f32,
+    show_strip: bool,
+    colormap: f32,
+    floor: f32,
+    /// Knob position of the style of the traces, the line or one of the band resolutions
+    style: f32,
+    peak_hold: bool,
+    fill: f32,
+    fill_opacity: f32,
+    line_width: f32,
+    /// The group of knobs below the traces
+    section: Section,
+}
+
+impl UIData {
+    /// Hands the resolution chosen by the knobs to the DSP threads
+    fn write_settings(&self) {
+        if let Ok(mut settings) = self.settings.lock() {
+            settings.window_size = window_size_from_normal(self.window_size);
+            settings.padding = padding_from_normal(self.padding);
+            settings.window = window_from_normal(self.window_function, self.kaiser_beta);
+            settings.backend = backend_from_normal(self.backend, self.bins_per_octave);
+        }
+    }
 }
 
 impl Model for UIData {
     fn event(&mut self, _cx: &mut Context, event: &mut Event) {
         event.map(|e, _| match e {
-            Events::Update(data) => {
-                self.data = data.clone();
+            Events::Update(idx, frame) => {
+                if let Some(data) = self.data.get_mut(*idx) {
+                    *data = frame.clone();
+                }
+            }
+            Events::UpdateCorrelation(data) => {
+                self.correlation = data.clone();
+            }
+            Events::StatusChange(status) => {
+                self.status = status.to_string();
+
+                // The frequency axes follow the sample rate of the server
+                match status {
+                    Status::Connected { sample_rate, .. } => self.sr = *sample_rate,
+                    Status::Playback {
+                        sample_rate,
+                        position,
+                        duration,
+                        paused,
+                    } => {
+                        self.sr = *sample_rate;
+                        self.position = if *duration > 0. {
+                            position / duration
+                        } else {
+                            0.
+                        };
+                        self.paused = *paused;
+                    }
+                    Status::Waiting(_) => {}
+                }
+            }
+            Events::UpdatePorts(ports) => {
+                self.ports = ports.clone();
+            }
+            Events::TogglePorts => {
+                self.show_ports = !self.show_ports;
+            }
+            Events::TogglePortConnection(source, input) => {
+                let command = if self.ports.is_connected(source, *input) {
+                    PortCommand::Disconnect {
+                        source: source.clone(),
+                        input: *input,
+                    }
+                } else {
+                    PortCommand::Connect {
+                        source: source.clone(),
+                        input: *input,
+                    }
+                };
+
+                // The port picker updates once the server reports the new connection
+                self.port_commands.send(command).unwrap_or(());
+            }
+            Events::TogglePause => {
+                if let Some(transport) = &self.transport {
+                    transport.send(TransportCommand::TogglePause).unwrap_or(());
+                }
+            }
+            Events::Seek(x) => {
+                self.position = *x;
+                if let Some(transport) = &self.transport {
+                    transport.send(TransportCommand::Seek(*x)).unwrap_or(());
+                }
+            }
+            Events::ToggleSpectrogram => {
+                self.show_spectrogram = !self.show_spectrogram;
+            }
+            Events::ToggleScroll => {
+                self.scroll_sideways = !self.scroll_sideways;
+            }
+            Events::ToggleStrip => {
+                self.show_strip = !self.show_strip;
+            }
+            Events::ColormapChange(x) => {
+                self.colormap = *x;
+            }
+            Events::FloorChange(x) => {
+                self.floor = *x;
+            }
+            Events::StyleChange(x) => {
+                self.style = *x;
+            }
+            Events::TogglePeakHold => {
+                self.peak_hold = !self.peak_hold;
+            }
+            Events::FillChange(x) => {
+                self.fill = *x;
+            }
+            Events::FillOpacityChange(x) => {
+                self.fill_opacity = *x;
+            }
+            Events::LineWidthChange(x) => {
+                self.line_width = *x;
+            }
+            Events::HistoryChange(x) => {
+                self.history = *x;
+            }
+            Events::SpeedChange(x) => {
+                self.speed = *x;
+            }
+            Events::ToggleTrace(idx) => {
+                if let Some(visible) = self.visible.get_mut(*idx) {
+                    *visible = !*visible;
+                }
             }
             Events::AttackChange(x) => {
                 self.attack = *x;
@@ -74,29 +297,151 @@ impl Model for UIData {
             Events::SlopeChange(x) => {
                 self.slope = *x;
             }
+            Events::WindowSizeChange(x) => {
+                self.window_size = *x;
+                self.write_settings();
+            }
+            Events::PaddingChange(x) => {
+                self.padding = *x;
+                self.write_settings();
+            }
+            Events::WindowFunctionChange(x) => {
+                self.window_function = *x;
+                self.write_settings();
+            }
+            Events::KaiserBetaChange(x) => {
+                self.kaiser_beta = *x;
+                self.write_settings();
+            }
+            Events::BackendChange(x) => {
+                self.backend = *x;
+                self.write_settings();
+            }
+            Events::BinsPerOctaveChange(x) => {
+                self.bins_per_octave = *x;
+                self.write_settings();
+            }
+            Events::SectionChange(section) => {
+                self.section = *section;
+            }
         });
     }
 }
 
 pub enum Events {
-    Update(Vec<f32>),
+    /// A new frame for the trace with the given index
+    Update(usize, SpectrumFrame),
+    UpdateCorrelation(SpectrumFrame),
+    /// The connection to the audio server changed
+    StatusChange(Status),
+    /// The ports of the server or their connections changed
+    UpdatePorts(Ports),
+    TogglePorts,
+    /// Connects or disconnects a source from the input with the given index
+    TogglePortConnection(String, usize),
+    /// Pauses or resumes the file
+    TogglePause,
+    /// Jumps to a position in the file, 0 is the start and 1 the end
+    Seek(f32),
+    ToggleSpectrogram,
+    ToggleScroll,
+    ToggleStrip,
+    ColormapChange(f32),
+    FloorChange(f32),
+    StyleChange(f32),
+    TogglePeakHold,
+    FillChange(f32),
+    FillOpacityChange(f32),
+    LineWidthChange(f32),
+    HistoryChange(f32),
+    SpeedChange(f32),
+    ToggleTrace(usize),
     AttackChange(f32),
     ReleaseChange(f32),
     MinChange(f32),
     MaxChange(f32),
     SlopeChange(f32),
+    WindowSizeChange(f32),
+    PaddingChange(f32),
+    WindowFunctionChange(f32),
+    KaiserBetaChange(f32),
+    BackendChange(f32),
+    BinsPerOctaveChange(f32),
+    SectionChange(Section),
 }
 
-pub fn ui(delivery_mutex: Arc<Mutex<Vec<f32>>>, sampling_rate: usize) {
+pub fn ui(
+    traces: Vec<Trace>,
+    correlation_delivery: Option<Output<SpectrumFrame>>,
+    settings: Arc<Mutex<AnalysisSettings>>,
+    backend: BackendLink,
+    sampling_rate: usize,
+) {
+    let channels = traces.len();
+    let names: Vec<String> = traces.iter().map(|trace| trace.name.clone()).collect();
+    let show_correlation = correlation_delivery.is_some();
+    let show_transport = backend.transport.is_some();
+    let BackendLink {
+        status,
+        ports,
+        port_commands,
+        transport,
+    } = backend;
+
+    // on_idle only gets shared access, but reading a triple buffer hands over a buffer
+    let traces = RefCell::new(traces);
+    let correlation_delivery = RefCell::new(correlation_delivery);
+    let shown_status = RefCell::new(None);
+    let shown_ports = RefCell::new(Ports::default());
+
+    let initial_settings = match settings.lock() {
+        Ok(settings) => *settings,
+        Err(_) => AnalysisSettings::default(),
+    };
+    let initial_window_size = normal_from_window_size(initial_settings.window_size);
+    let initial_padding = normal_from_padding(initial_settings.padding);
+    let (initial_window_function, initial_kaiser_beta) =
+        normal_from_window(initial_settings.window);
+    let (initial_backend, initial_bins_per_octave) = normal_from_backend(initial_settings.backend);
+
     Application::new(move |cx| {
         UIData {
-            data: vec![-90.; crate::FFT_SIZE],
+            data: vec![SpectrumFrame::empty(sampling_rate); channels],
+            visible: vec![true; channels],
+            correlation: SpectrumFrame::empty(sampling_rate),
             attack: 0.5,
             release: 0.9,
             sr: sampling_rate,
             min_freq: 0.,
             max_freq: 1.,
             slope: 0.0,
+            window_size: initial_window_size,
+            padding: initial_padding,
+            window_function: initial_window_function,
+            kaiser_beta: initial_kaiser_beta,
+            backend: initial_backend,
+            bins_per_octave: initial_bins_per_octave,
+            settings: settings.clone(),
+            status: String::new(),
+            ports: Ports::default(),
+            show_ports: false,
+            port_commands: port_commands.clone(),
+            position: 0.,
+            paused: false,
+            transport: transport.clone(),
+            show_spectrogram: false,
+            scroll_sideways: false,
+            history: 0.2,
+            speed: 0.3,
+            show_strip: false,
+            colormap: normal_from_colormap(Colormap::Inferno),
+            floor: normal_from_floor(-90.),
+            style: 0.,
+            peak_hold: true,
+            fill: normal_from_fill(Fill::None),
+            fill_opacity: 0.3,
+            line_width: normal_from_line_width(2.),
+            section: Section::Analysis,
         }
         .build(cx);
 
@@ -106,59 +451,361 @@ pub fn ui(delivery_mutex: Arc<Mutex<Vec<f32>>>, sampling_rate: usize) {
             ZStack::new(cx, |cx| {
                 FrequencyMarkers::new(cx, sampling_rate)
                     .min(UIData::min_freq)
-                    .max(UIData::max_freq);
+                    .max(UIData::max_freq)
+                    .sample_rate(UIData::sr);
 
                 VolumeMarkers::new(cx);
 
-                Spectrometer::new(
-                    cx,
-                    UIData::data,
-                    sampling_rate,
-                    Style::Spectrum,
-                    Scale::Logarithmic,
-                    vizia::vg::Color::hex("#f54e47"),
-                )
-                .attack(UIData::attack)
-                .release(UIData::release)
-                .min(UIData::min_freq)
-                .max(UIData::max_freq)
-                .slope(UIData::slope);
+                // One trace per channel, all drawn on top of each other
+                for channel in 0..channels {
+                    Spectrometer::new(
+                        cx,
+                        UIData::data.map(move |data| data[channel].clone()),
+                        sampling_rate,
+                        Style::Spectrum,
+                        Scale::Logarithmic,
+                        channel_color(channel),
+                    )
+                    .attack(UIData::attack)
+                    .release(UIData::release)
+                    .min(UIData::min_freq)
+                    .max(UIData::max_freq)
+                    .slope(UIData::slope)
+                    .style(UIData::style.map(|e| style_from_normal(*e)))
+                    .peak_hold(UIData::peak_hold)
+                    .fill(UIData::fill.map(|e| fill_from_normal(*e)))
+                    .fill_opacity(UIData::fill_opacity)
+                    .line_width(UIData::line_width.map(|e| line_width_from_normal(*e)))
+                    .visible(UIData::visible.map(move |visible| visible[channel]));
+                }
             })
-            .height(Percentage(80.));
+            .height(Percentage(if show_correlation { 65. } else { 80. }));
+
+            // The history of the first trace, below its spectrum so the frequencies line up
+            Binding::new(cx, UIData::show_spectrogram, move |cx, show_spectrogram| {
+                if show_spectrogram.get(cx) {
+                    ZStack::new(cx, |cx| {
+                        Spectrogram::new(
+                            cx,
+                            UIData::data.map(|data| data[0].clone()),
+                            sampling_rate,
+                            Scroll::Vertical,
+                            Scale::Logarithmic,
+                        )
+                        .min(UIData::min_freq)
+                        .max(UIData::max_freq)
+                        .history(UIData::history.map(|e| history_from_normal(*e)))
+                        .speed(UIData::speed.map(|e| speed_from_normal(*e)))
+                        .colormap(UIData::colormap.map(|e| colormap_from_normal(*e)))
+                        .floor(UIData::floor.map(|e| floor_from_normal(*e)))
+                        .scroll(UIData::scroll_sideways.map(|sideways| {
+                            if *sideways {
+                                Scroll::Horizontal
+                            } else {
+                                Scroll::Vertical
+                            }
+                        }));
+
+                        FrequencyMarkers::new(cx, sampling_rate)
+                            .min(UIData::min_freq)
+                            .max(UIData::max_freq)
+                            .sample_rate(UIData::sr)
+                            .vertical(UIData::scroll_sideways);
+                    })
+                    .height(Percentage(30.));
+                }
+            });
+
+            // A compact strip of the first trace, the level of every frequency as a color
+            Binding::new(cx, UIData::show_strip, move |cx, show_strip| {
+                if show_strip.get(cx) {
+                    Spectrometer::new(
+                        cx,
+                        UIData::data.map(|data| data[0].clone()),
+                        sampling_rate,
+                        Style::Gradient,
+                        Scale::Logarithmic,
+                        channel_color(0),
+                    )
+                    .attack(UIData::attack)
+                    .release(UIData::release)
+                    .min(UIData::min_freq)
+                    .max(UIData::max_freq)
+                    .colormap(UIData::colormap.map(|e| colormap_from_normal(*e)))
+                    .floor(UIData::floor.map(|e| floor_from_normal(*e)))
+                    .height(Pixels(40.));
+                }
+            });
+
+            // The correlation goes from +1 at the top over 0 in the middle to -1 at the bottom
+            if show_correlation {
+                ZStack::new(cx, |cx| {
+                    FrequencyMarkers::new(cx, sampling_rate)
+                        .min(UIData::min_freq)
+                        .max(UIData::max_freq)
+                        .sample_rate(UIData::sr);
+
+                    Spectrometer::new(
+                        cx,
+                        UIData::correlation,
+                        sampling_rate,
+                        Style::Spectrum,
+                        Scale::Logarithmic,
+                        vizia::vg::Color::hex("#e0e0e0"),
+                    )
+                    .attack(UIData::attack)
+                    .release(UIData::release)
+                    .min(UIData::min_freq)
+                    .max(UIData::max_freq)
+                    .slope(0.);
+                })
+                .height(Percentage(15.));
+            }
+
+            if show_transport {
+                HStack::new(cx, |cx| {
+                    Button::new(
+                        cx,
+                        |cx| cx.emit(Events::TogglePause),
+                        |cx| {
+                            Label::new(
+                                cx,
+                                UIData::paused.map(|paused| match paused {
+                                    true => "Play".to_string(),
+                                    false => "Pause".to_string(),
+                                }),
+                            )
+                        },
+                    );
+                    Slider::new(cx, UIData::position)
+                        .width(Stretch(1.))
+                        .on_changing(|cx, val| cx.emit(Events::Seek(val)));
+                })
+                .height(Auto)
+                .col_between(Pixels(20.));
+            }
+
             HStack::new(cx, |cx| {
-                VStack::new(cx, |cx| {
-                    Knob::new(cx, 0., UIData::min_freq, false)
-                        .on_changing(move |cx, val| cx.emit(Events::MinChange(val)));
-                    Label::new(cx, "Min Hz");
-                });
-                VStack::new(cx, |cx| {
-                    Knob::new(cx, 1., UIData::max_freq, false)
-                        .on_changing(move |cx, val| cx.emit(Events::MaxChange(val)));
-                    Label::new(cx, "Max Hz");
-                });
-                VStack::new(cx, |cx| {
-                    Knob::new(cx, 0.5, UIData::attack, false)
-                        .on_changing(move |cx, val| cx.emit(Events::AttackChange(val)));
-                    Label::new(cx, "Attack");
-                });
-                VStack::new(cx, |cx| {
-                    Knob::new(cx, 0.9, UIData::release, false)
-                        .on_changing(move |cx, val| cx.emit(Events::ReleaseChange(val)));
-                    Label::new(cx, "Release");
-                });
-                VStack::new(cx, |cx| {
-                    Knob::new(cx, 0.0, UIData::slope, false)
-                        .on_changing(move |cx, val| cx.emit(Events::SlopeChange(val)));
-                    Label::new(cx, UIData::slope.map(|e| e * 4.5));
-                });
+                for (channel, name) in names.iter().enumerate() {
+                    HStack::new(cx, |cx| {
+                        Checkbox::new(cx, UIData::visible.map(move |visible| visible[channel]))
+                            .on_toggle(move |cx| cx.emit(Events::ToggleTrace(channel)));
+                        let (r, g, b) = CHANNEL_COLORS[channel % CHANNEL_COLORS.len()];
+                        Label::new(cx, name.as_str()).color(Color::rgb(r, g, b));
+                    })
+                    .col_between(Pixels(5.));
+                }
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(cx, UIData::show_spectrogram)
+                        .on_toggle(|cx| cx.emit(Events::ToggleSpectrogram));
+                    Label::new(cx, "Spectrogram");
+                })
+                .col_between(Pixels(5.));
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(cx, UIData::show_strip)
+                        .on_toggle(|cx| cx.emit(Events::ToggleStrip));
+                    Label::new(cx, "Strip");
+                })
+                .col_between(Pixels(5.));
+
+                HStack::new(cx, |cx| {
+                    Checkbox::new(cx, UIData::show_ports)
+                        .on_toggle(|cx| cx.emit(Events::TogglePorts));
+                    Label::new(cx, "Ports");
+                })
+                .col_between(Pixels(5.));
+
+                Label::new(cx, UIData::status);
+            })
+            .height(Auto)
+            .col_between(Pixels(20.));
+
+            Binding::new(cx, UIData::show_ports, |cx, show_ports| {
+                if show_ports.get(cx) {
+                    port_picker(cx);
+                }
             });
+            controls(cx, initial_settings);
         });
     })
     .on_idle(move |cx| {
-        if let Ok(x) = delivery_mutex.lock() {
-            cx.emit(Events::Update(x.clone()));
+        // Only frames that arrived since the last idle get sent, so the views only redraw on new data
+        for (idx, trace) in traces.borrow_mut().iter_mut().enumerate() {
+            if let Some(frame) = trace.delivery.read() {
+                cx.emit(Events::Update(idx, frame.clone()));
+            }
+        }
+
+        if let Some(correlation_delivery) = correlation_delivery.borrow_mut().as_mut() {
+            if let Some(frame) = correlation_delivery.read() {
+                cx.emit(Events::UpdateCorrelation(frame.clone()));
+            }
+        }
+
+        // Never wait on the connection thread, a busy lock just gets checked next time
+        if let Ok(status) = status.try_lock() {
+            if *shown_status.borrow() != Some(*status) {
+                shown_status.replace(Some(*status));
+                cx.emit(Events::StatusChange(*status));
+            }
+        }
+
+        if let Ok(ports) = ports.try_lock() {
+            if *shown_ports.borrow() != *ports {
+                shown_ports.replace(ports.clone());
+                cx.emit(Events::UpdatePorts(ports.clone()));
+            }
         }
     })
     .background_color(Color::rgb(14, 11, 12))
     .run();
 }
+
+/// Maps the positions of the min and max knobs to the shown frequency range
+///
+/// The views keep the knob positions instead of the frequencies, so the range can be remapped
+/// when the sample rate changes.
+fn frequency_range(min_normal: f32, max_normal: f32, sample_rate: f32) -> (f32, f32) {
+    let nyquist = sample_rate / 2.;
+    (
+        20. + min_normal * (sample_rate / 4.),
+        nyquist - (1. - max_normal) * nyquist,
+    )
+}
+
+/// Maps a knob position to a power of two window size between the minimum and maximum size
+fn window_size_from_normal(x: f32) -> usize {
+    let steps =
+        (AnalysisSettings::MAX_WINDOW_SIZE / AnalysisSettings::MIN_WINDOW_SIZE).trailing_zeros();
+    AnalysisSettings::MIN_WINDOW_SIZE << (x * steps as f32).round() as usize
+}
+
+fn normal_from_window_size(window_size: usize) -> f32 {
+    let steps =
+        (AnalysisSettings::MAX_WINDOW_SIZE / AnalysisSettings::MIN_WINDOW_SIZE).trailing_zeros();
+    (window_size / AnalysisSettings::MIN_WINDOW_SIZE).trailing_zeros() as f32 / steps as f32
+}
+
+/// Maps a knob position to a power of two padding factor between 1 and the maximum padding
+fn padding_from_normal(x: f32) -> usize {
+    let steps = AnalysisSettings::MAX_PADDING.trailing_zeros();
+    1 << (x * steps as f32).round() as usize
+}
+
+fn normal_from_padding(padding: usize) -> f32 {
+    let steps = AnalysisSettings::MAX_PADDING.trailing_zeros();
+    padding.trailing_zeros() as f32 / steps as f32
+}
+
+/// Maps the window knob to one of the window functions and the beta knob to the kaiser beta
+fn window_from_normal(x: f32, beta: f32) -> WindowFunction {
+    let idx = (x * (WindowFunction::COUNT - 1) as f32).round() as usize;
+    WindowFunction::from_index(idx, beta * MAX_KAISER_BETA)
+}
+
+fn normal_from_window(window: WindowFunction) -> (f32, f32) {
+    let idx = (0..WindowFunction::COUNT)
+        .position(|idx| WindowFunction::from_index(idx, 0.).name() == window.name())
+        .unwrap_or(0);
+    let beta = match window {
+        WindowFunction::Kaiser(beta) => beta / MAX_KAISER_BETA,
+        _ => DEFAULT_KAISER_BETA / MAX_KAISER_BETA,
+    };
+
+    (idx as f32 / (WindowFunction::COUNT - 1) as f32, beta)
+}
+
+/// Maps the backend knob to one of the backends, the constant-Q transform gets the selected resolution
+fn backend_from_normal(x: f32, bins_per_octave: f32) -> Backend {
+    match (x * 2.).round() as usize {
+        0 => Backend::Fft,
+        1 => Backend::MultiResolution,
+        _ => Backend::ConstantQ(bins_per_octave_from_normal(bins_per_octave)),
+    }
+}
+
+fn normal_from_backend(backend: Backend) -> (f32, f32) {
+    match backend {
+        Backend::Fft => (0., 0.),
+        Backend::MultiResolution => (0.5, 0.),
+        Backend::ConstantQ(bins_per_octave) => {
+            let idx = BINS_PER_OCTAVE
+                .iter()
+                .position(|e| *e == bins_per_octave)
+                .unwrap_or(0);
+            (1., idx as f32 / (BINS_PER_OCTAVE.len() - 1) as f32)
+        }
+    }
+}
+
+fn bins_per_octave_from_normal(x: f32) -> usize {
+    BINS_PER_OCTAVE[(x * (BINS_PER_OCTAVE.len() - 1) as f32).round() as usize]
+}
+
+/// Maps the history knob linearly to the amount of rows the spectrogram keeps
+fn history_from_normal(x: f32) -> usize {
+    MIN_HISTORY + (x * (MAX_HISTORY - MIN_HISTORY) as f32).round() as usize
+}
+
+/// Maps the speed knob linearly to the rows the spectrogram adds per second
+fn speed_from_normal(x: f32) -> f32 {
+    MIN_ROWS_PER_SECOND + x * (MAX_ROWS_PER_SECOND - MIN_ROWS_PER_SECOND)
+}
+
+/// Maps the style knob to the line or to bars with one of the band resolutions
+fn style_from_normal(x: f32) -> Style {
+    match (x * BANDS_PER_OCTAVE.len() as f32).round() as usize {
+        0 => Style::Spectrum,
+        idx => Style::Bars(BANDS_PER_OCTAVE[idx - 1]),
+    }
+}
+
+/// Maps the fill knob to what gets drawn below the line
+fn fill_from_normal(x: f32) -> Fill {
+    Fill::from_index((x * (Fill::COUNT - 1) as f32).round() as usize)
+}
+
+fn normal_from_fill(fill: Fill) -> f32 {
+    let idx = (0..Fill::COUNT)
+        .position(|idx| Fill::from_index(idx) == fill)
+        .unwrap_or(0);
+    idx as f32 / (Fill::COUNT - 1) as f32
+}
+
+/// Maps the line width knob linearly to the width of the line in pixels
+fn line_width_from_normal(x: f32) -> f32 {
+    MIN_LINE_WIDTH + x * (MAX_LINE_WIDTH - MIN_LINE_WIDTH)
+}
+
+fn normal_from_line_width(line_width: f32) -> f32 {
+    (line_width - MIN_LINE_WIDTH) / (MAX_LINE_WIDTH - MIN_LINE_WIDTH)
+}
+
+/// Maps the colormap knob to one of the colormaps
+fn colormap_from_normal(x: f32) -> Colormap {
+    Colormap::from_index((x * (Colormap::COUNT - 1) as f32).round() as usize)
+}
+
+fn normal_from_colormap(colormap: Colormap) -> f32 {
+    let idx = (0..Colormap::COUNT)
+        .position(|idx| Colormap::from_index(idx) == colormap)
+        .unwrap_or(0);
+    idx as f32 / (Colormap::COUNT - 1) as f32
+}
+
+/// Maps the floor knob linearly to the level in dB that gets the quietest color
+fn floor_from_normal(x: f32) -> f32 {
+    MIN_FLOOR + x * (MAX_FLOOR - MIN_FLOOR)
+}
+
+fn normal_from_floor(floor: f32) -> f32 {
+    (floor - MIN_FLOOR) / (MAX_FLOOR - MIN_FLOOR)
+}
+
+/// The color of the trace with the given index
+fn channel_color(channel: usize) -> vizia::vg::Color {
+    let (r, g, b) = CHANNEL_COLORS[channel % CHANNEL_COLORS.len()];
+    vizia::vg::Color::rgb(r, g, b)
+}
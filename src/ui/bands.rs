@@ -0,0 +1,233 @@
+use std::time::{Duration, Instant};
+
+/// Ratio between the center frequencies of neighbouring octave bands, base ten as in IEC 61260
+const OCTAVE_RATIO: f32 = 1.995_262_3;
+/// Every band series has a band centered here
+const REFERENCE_FREQUENCY: f32 = 1000.;
+/// Level of silence, the spectra never go below it
+const FLOOR: f32 = -90.;
+
+/// The band resolutions the bars can be drawn with, in bands per octave
+pub const BANDS_PER_OCTAVE: &[usize] = &[1, 3, 6, 12, 24];
+
+/// A fractional octave band, all frequencies in Hz
+#[derive(Clone, Copy)]
+pub struct Band {
+    pub center: f32,
+    pub low: f32,
+    pub high: f32,
+}
+
+/// The bands with the given amount of bands per octave whose centers lie between min and max
+///
+/// The centers are the exact midband frequencies of IEC 61260, the ISO 266 values like
+/// 31.5 Hz or 12.5 kHz are these rounded for printing.
+pub fn octave_bands(bands_per_octave: usize, min: f32, max: f32) -> Vec<Band> {
+    let b = bands_per_octave as f32;
+
+    // Odd series have a band at the reference, even ones have band edges there
+    let center = |x: i32| {
+        let exponent = if bands_per_octave % 2 == 1 {
+            x as f32 / b
+        } else {
+            (2 * x + 1) as f32 / (2. * b)
+        };
+        REFERENCE_FREQUENCY * OCTAVE_RATIO.powf(exponent)
+    };
+    let half_band = OCTAVE_RATIO.powf(1. / (2. * b));
+
+    let lowest = (b * (min / REFERENCE_FREQUENCY).log(OCTAVE_RATIO)).floor() as i32 - 1;
+    let highest = (b * (max / REFERENCE_FREQUENCY).log(OCTAVE_RATIO)).ceil() as i32 + 1;
+
+    (lowest..=highest)
+        .map(center)
+        .filter(|center| *center >= min && *center <= max)
+        .map(|center| Band {
+            center,
+            low: center / half_band,
+            high: center * half_band,
+        })
+        .collect()
+}
+
+/// The level of a band in dB, given the bins of a spectrum sorted by frequency
+///
+/// Adds up the power of the bins in the band, divided by the amount of bins the power of a
+/// tone spreads over. So a tone reads its own level and pink noise reads the same in every
+/// band of a series, no matter the window, the transform size or the zero padding, like on
+/// an RTA. Bands narrower than the bin spacing show the bin closest to their center.
+pub fn band_level(band: &Band, frequencies: &[f32], magnitudes: &[f32], bins_per_tone: f32) -> f32 {
+    let start = frequencies.partition_point(|frequency| *frequency < band.low);
+    let end = frequencies.partition_point(|frequency| *frequency < band.high);
+
+    if start < end {
+        // Bins at the floor are silent, adding them up would lift the floor of wide bands
+        let power: f32 = magnitudes[start..end]
+            .iter()
+            .filter(|magnitude| **magnitude > FLOOR)
+            .map(|magnitude| 10_f32.powf(magnitude / 10.))
+            .sum();
+
+        return (10. * (power / bins_per_tone).log10()).max(FLOOR);
+    }
+
+    // The neighbours of the empty band, one below and one above it
+    let below = start.checked_sub(1);
+    let above = (start < frequencies.len()).then_some(start);
+    let distance = |idx: usize| (frequencies[idx] / band.center).log2().abs();
+
+    match (below, above) {
+        (Some(below), Some(above)) if distance(below) < distance(above) => magnitudes[below],
+        (_, Some(above)) => magnitudes[above],
+        (Some(below), None) => magnitudes[below],
+        (None, None) => FLOOR,
+    }
+}
+
+/// How long a peak stays put before it starts to fall
+const PEAK_HOLD: Duration = Duration::from_secs(1);
+/// How fast a peak falls after the hold time, in dB per second
+const PEAK_FALL: f32 = 20.;
+
+/// The cap above a bar that marks the loudest level of the last moments
+#[derive(Clone, Copy)]
+pub struct Peak {
+    level: f32,
+    /// When the level was reached
+    since: Instant,
+}
+
+impl Peak {
+    pub fn new(level: f32) -> Self {
+        Peak {
+            level,
+            since: Instant::now(),
+        }
+    }
+
+    /// Jumps up to louder levels, holds and then falls towards quieter ones
+    pub fn update(&mut self, level: f32, now: Instant) {
+        let held = now.saturating_duration_since(self.since);
+
+        let fallen = match held.checked_sub(PEAK_HOLD) {
+            Some(falling) => self.level - falling.as_secs_f32() * PEAK_FALL,
+            None => self.level,
+        };
+
+        if level >= fallen {
+            self.level = level;
+            self.since = now;
+        } else if held > PEAK_HOLD {
+            // Falls from where it is now, so the next fall starts at this level
+            self.level = fallen;
+            self.since = now - PEAK_HOLD;
+        }
+    }
+
+    pub fn level(&self) -> f32 {
+        self.level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{band_level, octave_bands, Peak, FLOOR};
+
+    /// Whether the series has a band within 1% of the nominal ISO 266 frequency
+    fn has_band(bands_per_octave: usize, nominal: f32) -> bool {
+        octave_bands(bands_per_octave, 20., 20000.)
+            .iter()
+            .any(|band| (band.center / nominal - 1.).abs() < 0.01)
+    }
+
+    #[test]
+    fn iso_center_frequencies() {
+        for nominal in [
+            31.5, 63., 125., 250., 500., 1000., 2000., 4000., 8000., 16000.,
+        ] {
+            assert!(has_band(1, nominal), "no octave band at {} Hz", nominal);
+        }
+        for nominal in [25., 31.5, 40., 50., 1000., 1250., 12500.] {
+            assert!(
+                has_band(3, nominal),
+                "no third octave band at {} Hz",
+                nominal
+            );
+        }
+
+        // The reference is exact, the others are rounded for printing
+        let bands = octave_bands(3, 900., 1100.);
+        assert_eq!(bands.len(), 1);
+        assert!((bands[0].center - 1000.).abs() < 0.01);
+    }
+
+    #[test]
+    fn bands_touch() {
+        let bands = octave_bands(6, 20., 20000.);
+
+        for pair in bands.windows(2) {
+            assert!((pair[0].high / pair[1].low - 1.).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn full_scale_tone_reads_0db() {
+        // A full scale sine on bin 100 through a Hann window, the neighbours get half the amplitude
+        let frequencies: Vec<f32> = (0..2400).map(|bin| bin as f32 * 10.).collect();
+        let mut magnitudes = vec![FLOOR; frequencies.len()];
+        magnitudes[99] = -6.0206;
+        magnitudes[100] = 0.;
+        magnitudes[101] = -6.0206;
+        let bins_per_tone = 1.5;
+
+        let bands = octave_bands(3, 20., 20000.);
+        let own = bands
+            .iter()
+            .find(|band| band.low <= 1000. && band.high > 1000.)
+            .unwrap();
+        let level = band_level(own, &frequencies, &magnitudes, bins_per_tone);
+        assert!(level.abs() < 0.01, "tone reads {} dB", level);
+
+        let next = bands.iter().find(|band| band.low > 1100.).unwrap();
+        assert_eq!(
+            band_level(next, &frequencies, &magnitudes, bins_per_tone),
+            FLOOR
+        );
+    }
+
+    #[test]
+    fn narrow_band_takes_the_nearest_bin() {
+        let frequencies = [600., 900., 1200., 1500.];
+        let magnitudes = [-40., -30., -20., -10.];
+
+        // The 1/24 octave band at 986 Hz lies between the bins at 900 and 1200 Hz
+        let band = octave_bands(24, 980., 990.)[0];
+        assert_eq!(band_level(&band, &frequencies, &magnitudes, 1.), -30.);
+    }
+
+    #[test]
+    fn peak_holds_and_falls() {
+        let start = Instant::now();
+        let at = |secs: f32| start + Duration::from_secs_f32(secs);
+
+        let mut peak = Peak::new(FLOOR);
+        peak.update(-10., at(0.));
+        assert_eq!(peak.level(), -10.);
+
+        // Held for a second
+        peak.update(-30., at(0.5));
+        assert_eq!(peak.level(), -10.);
+
+        // Then falls at 20 dB per second, until it meets the level
+        peak.update(-30., at(1.5));
+        assert!((peak.level() + 20.).abs() < 0.01);
+        peak.update(-30., at(2.5));
+        assert_eq!(peak.level(), -30.);
+
+        // Louder levels are taken right away
+        peak.update(-5., at(2.6));
+        assert_eq!(peak.level(), -5.);
+    }
+}
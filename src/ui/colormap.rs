@@ -0,0 +1,197 @@
+use vizia::prelude::Data;
+use vizia::vg::Color;
+
+/// Evenly spaced samples of the matplotlib colormaps, from the quietest to the loudest level
+const VIRIDIS: &[(u8, u8, u8)] = &[
+    (0x44, 0x01, 0x54),
+    (0x48, 0x25, 0x76),
+    (0x41, 0x44, 0x87),
+    (0x35, 0x60, 0x8d),
+    (0x2a, 0x78, 0x8e),
+    (0x21, 0x90, 0x8c),
+    (0x22, 0xa8, 0x84),
+    (0x43, 0xbf, 0x71),
+    (0x7a, 0xd1, 0x51),
+    (0xbb, 0xdf, 0x27),
+    (0xfd, 0xe7, 0x25),
+];
+const MAGMA: &[(u8, u8, u8)] = &[
+    (0x00, 0x00, 0x04),
+    (0x14, 0x0e, 0x36),
+    (0x3b, 0x0f, 0x70),
+    (0x64, 0x1a, 0x80),
+    (0x8c, 0x29, 0x81),
+    (0xb7, 0x37, 0x79),
+    (0xde, 0x49, 0x68),
+    (0xf7, 0x70, 0x5c),
+    (0xfe, 0x9f, 0x6d),
+    (0xfe, 0xcf, 0x92),
+    (0xfc, 0xfd, 0xbf),
+];
+const INFERNO: &[(u8, u8, u8)] = &[
+    (0x00, 0x00, 0x04),
+    (0x16, 0x0b, 0x39),
+    (0x42, 0x0a, 0x68),
+    (0x6a, 0x17, 0x6e),
+    (0x93, 0x26, 0x67),
+    (0xbc, 0x37, 0x54),
+    (0xdd, 0x51, 0x3a),
+    (0xf3, 0x78, 0x19),
+    (0xfc, 0xa5, 0x0a),
+    (0xf6, 0xd7, 0x46),
+    (0xfc, 0xff, 0xa4),
+];
+const GRAYSCALE: &[(u8, u8, u8)] = &[(0, 0, 0), (255, 255, 255)];
+/// Black over red and yellow to white, like the old analog displays
+const HEAT: &[(u8, u8, u8)] = &[
+    (0, 0, 0),
+    (128, 0, 0),
+    (255, 32, 0),
+    (255, 160, 0),
+    (255, 255, 64),
+    (255, 255, 255),
+];
+
+/// Turns levels into colors for the gradient strip and the spectrogram
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Colormap {
+    Viridis,
+    Magma,
+    Inferno,
+    Grayscale,
+    Heat,
+}
+
+impl Colormap {
+    pub const COUNT: usize = 5;
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => Colormap::Viridis,
+            1 => Colormap::Magma,
+            2 => Colormap::Inferno,
+            3 => Colormap::Grayscale,
+            _ => Colormap::Heat,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colormap::Viridis => "Viridis",
+            Colormap::Magma => "Magma",
+            Colormap::Inferno => "Inferno",
+            Colormap::Grayscale => "Grayscale",
+            Colormap::Heat => "Heat",
+        }
+    }
+
+    fn samples(&self) -> &'static [(u8, u8, u8)] {
+        match self {
+            Colormap::Viridis => VIRIDIS,
+            Colormap::Magma => MAGMA,
+            Colormap::Inferno => INFERNO,
+            Colormap::Grayscale => GRAYSCALE,
+            Colormap::Heat => HEAT,
+        }
+    }
+
+    /// The color of a normalized level, 0 is the quietest and 1 the loudest
+    pub fn color(&self, level: f32) -> Color {
+        let samples = self.samples();
+        let position = level.clamp(0., 1.) * (samples.len() - 1) as f32;
+        let idx = (position as usize).min(samples.len() - 2);
+        let t = position - idx as f32;
+
+        let (r0, g0, b0) = samples[idx];
+        let (r1, g1, b1) = samples[idx + 1];
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t) / 255.;
+
+        Color::rgbf(mix(r0, r1), mix(g0, g1), mix(b0, b1))
+    }
+}
+
+impl Data for Colormap {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// Level that gets the loudest color of the colormap
+const CEILING_DB: f32 = 0.;
+
+/// Where a level in dB lies between the floor at 0 and the ceiling at 1, clamped to that range
+pub fn normalize_db(db: f32, floor: f32, ceiling: f32) -> f32 {
+    ((db - floor) / (ceiling - floor)).clamp(0., 1.)
+}
+
+/// Gradient stops for levels along an axis that is extent pixels long, from the floor up to 0dB
+///
+/// Takes the normalized positions of the levels in ascending order. Levels that end up on
+/// the same pixel get merged into their loudest one, so dense high frequency bins don't
+/// turn into thousands of stops.
+pub fn gradient_stops(
+    levels: impl Iterator<Item = (f32, f32)>,
+    extent: f32,
+    colormap: Colormap,
+    floor: f32,
+) -> Vec<(f32, Color)> {
+    let mut stops: Vec<(f32, f32)> = Vec::new();
+    let mut last_pixel = None;
+
+    for (position, db) in levels {
+        let pixel = (position * extent) as usize;
+
+        match stops.last_mut() {
+            Some(last) if last_pixel == Some(pixel) => last.1 = last.1.max(db),
+            _ => stops.push((position, db)),
+        }
+        last_pixel = Some(pixel);
+    }
+
+    stops
+        .into_iter()
+        .map(|(position, db)| {
+            (
+                position,
+                colormap.color(normalize_db(db, floor, CEILING_DB)),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use vizia::vg::Color;
+
+    use super::{gradient_stops, Colormap, CEILING_DB};
+
+    #[test]
+    fn endpoints_are_the_first_and_last_sample() {
+        for idx in 0..Colormap::COUNT {
+            let colormap = Colormap::from_index(idx);
+            let samples = colormap.samples();
+            let (r, g, b) = samples[0];
+            let (r1, g1, b1) = samples[samples.len() - 1];
+
+            assert_eq!(colormap.color(0.), Color::rgb(r, g, b));
+            assert_eq!(colormap.color(1.), Color::rgb(r1, g1, b1));
+
+            // Levels outside the range are clamped
+            assert_eq!(colormap.color(-1.), colormap.color(0.));
+            assert_eq!(colormap.color(2.), colormap.color(1.));
+        }
+    }
+
+    #[test]
+    fn gradient_stops_merge_levels_on_one_pixel() {
+        let levels = [(0., -90.), (0.01, -10.), (0.5, -90.), (0.99, CEILING_DB)];
+        let stops = gradient_stops(levels.into_iter(), 10., Colormap::Grayscale, -90.);
+
+        // The first two levels share the first pixel, so the louder one wins
+        assert_eq!(stops.len(), 3);
+        assert_eq!(stops[0].0, 0.);
+        assert_eq!(stops[0].1, Colormap::Grayscale.color(80. / 90.));
+        assert_eq!(stops[1].1, Color::rgb(0, 0, 0));
+        assert_eq!(stops[2].1, Color::rgb(255, 255, 255));
+    }
+}
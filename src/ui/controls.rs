@@ -0,0 +1,271 @@
+use vizia::prelude::*;
+
+use crate::dsp::{AnalysisSettings, Backend};
+
+use super::colormap::Colormap;
+use super::spectrometer::{Fill, Style};
+use super::{
+    backend_from_normal, bins_per_octave_from_normal, colormap_from_normal, fill_from_normal,
+    floor_from_normal, history_from_normal, line_width_from_normal, normal_from_backend,
+    normal_from_colormap, normal_from_fill, normal_from_floor, normal_from_line_width,
+    normal_from_padding, normal_from_window, normal_from_window_size, padding_from_normal,
+    speed_from_normal, style_from_normal, window_from_normal, window_size_from_normal, Events,
+    UIData, MAX_KAISER_BETA,
+};
+
+/// A group of knobs, only one of them is shown at a time so the row fits the window
+#[derive(Clone, Copy, PartialEq)]
+pub enum Section {
+    Analysis,
+    Display,
+    Spectrogram,
+    Bars,
+}
+
+impl Section {
+    const ALL: [Section; 4] = [
+        Section::Analysis,
+        Section::Display,
+        Section::Spectrogram,
+        Section::Bars,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Section::Analysis => "Analysis",
+            Section::Display => "Display",
+            Section::Spectrogram => "Spectrogram",
+            Section::Bars => "Bars",
+        }
+    }
+}
+
+impl Data for Section {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// A tab per section and below it the knobs of the chosen section
+///
+/// The knobs get rebuilt when switching sections, they pick up their positions from UIData.
+pub fn controls(cx: &mut Context, initial: AnalysisSettings) {
+    HStack::new(cx, |cx| {
+        for section in Section::ALL {
+            Button::new(
+                cx,
+                move |cx| cx.emit(Events::SectionChange(section)),
+                move |cx| Label::new(cx, section.name()),
+            );
+        }
+    })
+    .height(Auto)
+    .col_between(Pixels(20.));
+
+    Binding::new(cx, UIData::section, move |cx, section| {
+        let section = section.get(cx);
+
+        HStack::new(cx, |cx| match section {
+            Section::Analysis => analysis(cx, initial),
+            Section::Display => display(cx),
+            Section::Spectrogram => spectrogram(cx),
+            Section::Bars => bars(cx),
+        });
+    });
+}
+
+/// How the spectrum gets computed, the knobs default to the settings from the command line
+fn analysis(cx: &mut Context, initial: AnalysisSettings) {
+    let initial_window_size = normal_from_window_size(initial.window_size);
+    let initial_padding = normal_from_padding(initial.padding);
+    let (initial_window_function, initial_kaiser_beta) = normal_from_window(initial.window);
+    let (initial_backend, initial_bins_per_octave) = normal_from_backend(initial.backend);
+
+    VStack::new(cx, |cx| {
+        Knob::new(cx, initial_window_size, UIData::window_size, false)
+            .on_changing(move |cx, val| cx.emit(Events::WindowSizeChange(val)));
+        Label::new(
+            cx,
+            UIData::window_size.map(|e| format!("{} Samples", window_size_from_normal(*e))),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, initial_padding, UIData::padding, false)
+            .on_changing(move |cx, val| cx.emit(Events::PaddingChange(val)));
+        // The constant-Q transform has no FFT size to pad, so the knob does nothing there
+        Binding::new(cx, UIData::backend, |cx, backend| {
+            let constant_q = matches!(
+                backend_from_normal(backend.get(cx), 0.),
+                Backend::ConstantQ(_)
+            );
+            Label::new(
+                cx,
+                UIData::padding.map(move |e| {
+                    if constant_q {
+                        "No Padding".to_string()
+                    } else {
+                        format!("{}x Padding", padding_from_normal(*e))
+                    }
+                }),
+            );
+        });
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, initial_window_function, UIData::window_function, false)
+            .on_changing(move |cx, val| cx.emit(Events::WindowFunctionChange(val)));
+        Label::new(
+            cx,
+            UIData::window_function.map(|e| window_from_normal(*e, 0.).name().to_string()),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, initial_kaiser_beta, UIData::kaiser_beta, false)
+            .on_changing(move |cx, val| cx.emit(Events::KaiserBetaChange(val)));
+        Label::new(
+            cx,
+            UIData::kaiser_beta.map(|e| format!("Kaiser beta {:.1}", *e * MAX_KAISER_BETA)),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, initial_backend, UIData::backend, false)
+            .on_changing(move |cx, val| cx.emit(Events::BackendChange(val)));
+        Label::new(
+            cx,
+            UIData::backend.map(|e| match backend_from_normal(*e, 0.) {
+                Backend::Fft => "FFT".to_string(),
+                Backend::MultiResolution => "Multi-Resolution".to_string(),
+                Backend::ConstantQ(_) => "Constant-Q".to_string(),
+            }),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, initial_bins_per_octave, UIData::bins_per_octave, false)
+            .on_changing(move |cx, val| cx.emit(Events::BinsPerOctaveChange(val)));
+        Label::new(
+            cx,
+            UIData::bins_per_octave
+                .map(|e| format!("{} Bins/Octave", bins_per_octave_from_normal(*e))),
+        );
+    });
+}
+
+/// How the traces get drawn
+fn display(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0., UIData::min_freq, false)
+            .on_changing(move |cx, val| cx.emit(Events::MinChange(val)));
+        Label::new(cx, "Min Hz");
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 1., UIData::max_freq, false)
+            .on_changing(move |cx, val| cx.emit(Events::MaxChange(val)));
+        Label::new(cx, "Max Hz");
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0.5, UIData::attack, false)
+            .on_changing(move |cx, val| cx.emit(Events::AttackChange(val)));
+        Label::new(cx, "Attack");
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0.9, UIData::release, false)
+            .on_changing(move |cx, val| cx.emit(Events::ReleaseChange(val)));
+        Label::new(cx, "Release");
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0.0, UIData::slope, false)
+            .on_changing(move |cx, val| cx.emit(Events::SlopeChange(val)));
+        Label::new(cx, UIData::slope.map(|e| e * 4.5));
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, normal_from_fill(Fill::None), UIData::fill, false)
+            .on_changing(move |cx, val| cx.emit(Events::FillChange(val)));
+        Label::new(
+            cx,
+            UIData::fill.map(|e| fill_from_normal(*e).name().to_string()),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0.3, UIData::fill_opacity, false)
+            .on_changing(move |cx, val| cx.emit(Events::FillOpacityChange(val)));
+        Label::new(
+            cx,
+            UIData::fill_opacity.map(|e| format!("Opacity {:.0}%", *e * 100.)),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, normal_from_line_width(2.), UIData::line_width, false)
+            .on_changing(move |cx, val| cx.emit(Events::LineWidthChange(val)));
+        Label::new(
+            cx,
+            UIData::line_width.map(|e| format!("Line {:.1}px", line_width_from_normal(*e))),
+        );
+    });
+}
+
+/// Colors and speed of the spectrogram, the colormap and floor also apply to the strip
+fn spectrogram(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0.2, UIData::history, false)
+            .on_changing(move |cx, val| cx.emit(Events::HistoryChange(val)));
+        Label::new(
+            cx,
+            UIData::history.map(|e| format!("{} Rows", history_from_normal(*e))),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0.3, UIData::speed, false)
+            .on_changing(move |cx, val| cx.emit(Events::SpeedChange(val)));
+        Label::new(
+            cx,
+            UIData::speed.map(|e| format!("{:.0} Rows/s", speed_from_normal(*e))),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(
+            cx,
+            normal_from_colormap(Colormap::Inferno),
+            UIData::colormap,
+            false,
+        )
+        .on_changing(move |cx, val| cx.emit(Events::ColormapChange(val)));
+        Label::new(
+            cx,
+            UIData::colormap.map(|e| colormap_from_normal(*e).name().to_string()),
+        );
+    });
+    VStack::new(cx, |cx| {
+        Knob::new(cx, normal_from_floor(-90.), UIData::floor, false)
+            .on_changing(move |cx, val| cx.emit(Events::FloorChange(val)));
+        Label::new(
+            cx,
+            UIData::floor.map(|e| format!("Floor {:.0}dB", floor_from_normal(*e))),
+        );
+    });
+    HStack::new(cx, |cx| {
+        Checkbox::new(cx, UIData::scroll_sideways).on_toggle(|cx| cx.emit(Events::ToggleScroll));
+        Label::new(cx, "Sideways");
+    })
+    .col_between(Pixels(5.));
+}
+
+/// The band resolution of the bars, peak hold only applies to bars
+fn bars(cx: &mut Context) {
+    VStack::new(cx, |cx| {
+        Knob::new(cx, 0., UIData::style, false)
+            .on_changing(move |cx, val| cx.emit(Events::StyleChange(val)));
+        Label::new(
+            cx,
+            UIData::style.map(|e| match style_from_normal(*e) {
+                Style::Bars(bands_per_octave) => {
+                    format!("1/{} Octave", bands_per_octave)
+                }
+                _ => "Line".to_string(),
+            }),
+        );
+    });
+    HStack::new(cx, |cx| {
+        Checkbox::new(cx, UIData::peak_hold).on_toggle(|cx| cx.emit(Events::TogglePeakHold));
+        Label::new(cx, "Peaks");
+    })
+    .col_between(Pixels(5.));
+}
@@ -1,6 +1,8 @@
 use vizia::prelude::*;
 use vizia::vg::{Paint, Path};
 
+use crate::ui::frequency_range;
+
 const C_FREQUENCIES: &[f32] = &[
     16.352, 32.703, 65.406, 130.813, 261.626, 523.251, 1046.502, 2093.005, 4186.009, 8372.018,
     16744.036,
@@ -9,13 +11,18 @@ const C_FREQUENCIES: &[f32] = &[
 enum FreqEvents {
     UpdateMin(f32),
     UpdateMax(f32),
+    UpdateSampleRate(usize),
+    UpdateVertical(bool),
 }
 
-#[allow(dead_code)]
 pub struct FrequencyMarkers {
     min_freq: f32,
     max_freq: f32,
+    min_normal: f32,
+    max_normal: f32,
     sr: f32,
+    /// Frequencies go from bottom to top instead of from left to right
+    vertical: bool,
 }
 
 impl FrequencyMarkers {
@@ -23,11 +30,18 @@ impl FrequencyMarkers {
         Self {
             min_freq: 20.,
             max_freq: sampling_rate as f32 / 2.,
+            min_normal: 0.,
+            max_normal: 1.,
             sr: sampling_rate as f32,
+            vertical: false,
         }
         .build(cx, |_cx| {})
     }
 
+    fn update_range(&mut self) {
+        (self.min_freq, self.max_freq) = frequency_range(self.min_normal, self.max_normal, self.sr);
+    }
+
     /// Maps [x0,x1] to [y0,y1] linearly at position val in [x0,x1]
     ///
     /// Source: https://tig.krj.st/spectrm/file/spectrm.c
@@ -48,13 +62,24 @@ impl FrequencyMarkers {
 }
 
 impl View for FrequencyMarkers {
-    fn event(&mut self, _cx: &mut Context, event: &mut Event) {
+    fn event(&mut self, cx: &mut Context, event: &mut Event) {
         event.map(|e, _| match e {
             FreqEvents::UpdateMin(x) => {
-                self.min_freq = 20. + x * (self.sr as f32 / 4.);
+                self.min_normal = *x;
+                self.update_range();
             }
             FreqEvents::UpdateMax(x) => {
-                self.max_freq = (self.sr as f32 / 2.) - (1. - x) * (self.sr as f32 / 2.);
+                self.max_normal = *x;
+                self.update_range();
+            }
+            FreqEvents::UpdateSampleRate(sr) => {
+                self.sr = *sr as f32;
+                self.update_range();
+                cx.style().needs_redraw = true;
+            }
+            FreqEvents::UpdateVertical(x) => {
+                self.vertical = *x;
+                cx.style().needs_redraw = true;
             }
         });
     }
@@ -85,11 +110,19 @@ impl View for FrequencyMarkers {
 
             let freq_text = format!("C{}", c_idx);
 
-            let x_pos = self.freq_to_pos(*freq) * width;
+            let res = if self.vertical {
+                let y_pos = height - self.freq_to_pos(*freq) * height;
 
-            path.move_to(x_pos, 0.);
-            path.line_to(x_pos, height);
-            let res = canvas.fill_text(x_pos, height, &freq_text, text_paint);
+                path.move_to(0., y_pos);
+                path.line_to(width, y_pos);
+                canvas.fill_text(0., y_pos, &freq_text, text_paint)
+            } else {
+                let x_pos = self.freq_to_pos(*freq) * width;
+
+                path.move_to(x_pos, 0.);
+                path.line_to(x_pos, height);
+                canvas.fill_text(x_pos, height, &freq_text, text_paint)
+            };
 
             match res {
                 Ok(_) => {}
@@ -106,6 +139,9 @@ impl View for FrequencyMarkers {
 pub trait FreqMarkerHandle {
     fn min(self, val: impl Res<f32>) -> Self;
     fn max(self, val: impl Res<f32>) -> Self;
+    fn sample_rate(self, val: impl Res<usize>) -> Self;
+    /// Puts the frequencies on the vertical axis, for views that scroll sideways
+    fn vertical(self, val: impl Res<bool>) -> Self;
 }
 
 impl FreqMarkerHandle for Handle<'_, FrequencyMarkers> {
@@ -124,4 +160,20 @@ impl FreqMarkerHandle for Handle<'_, FrequencyMarkers> {
 
         self
     }
+
+    fn sample_rate(self, val: impl Res<usize>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, FreqEvents::UpdateSampleRate(value));
+        });
+
+        self
+    }
+
+    fn vertical(self, val: impl Res<bool>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, FreqEvents::UpdateVertical(value));
+        });
+
+        self
+    }
 }
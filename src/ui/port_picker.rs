@@ -0,0 +1,74 @@
+use vizia::prelude::*;
+
+use crate::audio::Ports;
+
+use super::{Events, UIData};
+
+/// Width of the column with the source names
+const SOURCE_WIDTH: f32 = 400.;
+/// Width of the column of one input
+const INPUT_WIDTH: f32 = 150.;
+
+impl Data for Ports {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+/// Lists the audio outputs of the server with a checkbox per input to connect them
+///
+/// The whole list gets rebuilt when ports come or go, which happens rarely enough.
+pub fn port_picker(cx: &mut Context) {
+    Binding::new(cx, UIData::ports, |cx, ports| {
+        let ports = ports.get(cx);
+
+        VStack::new(cx, |cx| {
+            if ports.inputs.is_empty() {
+                Label::new(cx, "No ports to pick from");
+                return;
+            }
+
+            HStack::new(cx, |cx| {
+                Label::new(cx, "Source").width(Pixels(SOURCE_WIDTH));
+                for input in ports.inputs.iter() {
+                    Label::new(cx, short_name(input)).width(Pixels(INPUT_WIDTH));
+                }
+            })
+            .height(Auto);
+
+            for source in ports.sources.iter() {
+                HStack::new(cx, |cx| {
+                    Label::new(cx, source.as_str()).width(Pixels(SOURCE_WIDTH));
+
+                    for input in 0..ports.inputs.len() {
+                        let connected_source = source.clone();
+                        let toggled_source = source.clone();
+
+                        HStack::new(cx, |cx| {
+                            Checkbox::new(
+                                cx,
+                                UIData::ports
+                                    .map(move |ports| ports.is_connected(&connected_source, input)),
+                            )
+                            .on_toggle(move |cx| {
+                                cx.emit(Events::TogglePortConnection(toggled_source.clone(), input))
+                            });
+                        })
+                        .width(Pixels(INPUT_WIDTH))
+                        .child_space(Stretch(1.));
+                    }
+                })
+                .height(Auto);
+            }
+        })
+        .height(Auto)
+        .row_between(Pixels(5.));
+    });
+}
+
+/// The port name without the client name in front of it
+fn short_name(port_name: &str) -> &str {
+    port_name
+        .split_once(':')
+        .map_or(port_name, |(_, short_name)| short_name)
+}
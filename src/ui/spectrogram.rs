@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use vizia::prelude::*;
+use vizia::vg::{Paint, Path};
+
+use crate::dsp::{AnalysisSettings, SpectrumFrame};
+use crate::ui::colormap::{gradient_stops, Colormap};
+use crate::ui::frequency_range;
+use crate::ui::spectrometer::Scale;
+
+/// Which way the history moves
+#[derive(Clone, Copy, PartialEq)]
+pub enum Scroll {
+    /// Frequencies go from left to right, new rows come in at the top and move down
+    Vertical,
+    /// Frequencies go from bottom to top, new columns come in at the right and move left
+    Horizontal,
+}
+
+/// A waterfall of the past spectra of one trace
+///
+/// Every row holds the loudest level per bin of the frames that arrived since the previous
+/// row, so short events don't get lost when the DSP delivers more frames than rows are shown.
+pub struct Spectrogram {
+    /// Magnitudes of the past rows, the newest in front
+    history: VecDeque<Vec<f32>>,
+    /// Center frequencies of the bins, the same for all rows in the history
+    frequencies: Vec<f32>,
+    /// Loudest magnitude per bin since the last row was added
+    pending: Vec<f32>,
+    /// When the newest row was added
+    last_row: Option<Instant>,
+    /// The settings the history was analyzed with
+    settings: Option<AnalysisSettings>,
+    history_length: usize,
+    rows_per_second: f32,
+    sr: usize,
+    scroll: Scroll,
+    scale: Scale,
+    colormap: Colormap,
+    /// Level that gets the quietest color, everything below looks the same
+    floor: f32,
+    min_freq: f32,
+    max_freq: f32,
+    min_normal: f32,
+    max_normal: f32,
+}
+
+pub enum SpectrogramEvents {
+    Update(SpectrumFrame),
+    UpdateMin(f32),
+    UpdateMax(f32),
+    UpdateHistory(usize),
+    UpdateSpeed(f32),
+    UpdateColormap(Colormap),
+    UpdateFloor(f32),
+    UpdateScroll(Scroll),
+}
+
+impl Spectrogram {
+    pub fn new<L: Lens<Target = SpectrumFrame>>(
+        cx: &mut Context,
+        lens: L,
+        sampling_rate: usize,
+        scroll: Scroll,
+        scale: Scale,
+    ) -> Handle<Self> {
+        Self {
+            history: VecDeque::new(),
+            frequencies: Vec::new(),
+            pending: Vec::new(),
+            last_row: None,
+            settings: None,
+            history_length: 200,
+            rows_per_second: 20.,
+            sr: sampling_rate,
+            scroll,
+            scale,
+            colormap: Colormap::Inferno,
+            floor: -90.,
+            min_freq: 20.,
+            max_freq: sampling_rate as f32 / 2.,
+            min_normal: 0.,
+            max_normal: 1.,
+        }
+        .build(cx, move |cx| {
+            Binding::new(cx, lens, |cx, value| {
+                cx.emit(SpectrogramEvents::Update(value.get(cx)));
+            });
+        })
+    }
+
+    fn update_range(&mut self) {
+        (self.min_freq, self.max_freq) =
+            frequency_range(self.min_normal, self.max_normal, self.sr as f32);
+    }
+
+    /// Starts a new history for frames with other bins
+    fn reset(&mut self, frame: &SpectrumFrame) {
+        self.history.clear();
+        self.frequencies = frame.frequencies.clone();
+        self.pending = vec![f32::NEG_INFINITY; frame.len()];
+        self.last_row = None;
+        self.settings = Some(frame.settings);
+    }
+
+    /// Collects the frame and turns everything collected into a row once it is due
+    ///
+    /// Returns whether a row was added.
+    fn add(&mut self, frame: &SpectrumFrame) -> bool {
+        for (pending, magnitude) in self.pending.iter_mut().zip(frame.magnitudes.iter()) {
+            *pending = pending.max(*magnitude);
+        }
+
+        let interval = Duration::from_secs_f32(1. / self.rows_per_second);
+        let due = match self.last_row {
+            Some(last_row) => frame.timestamp.duration_since(last_row) >= interval,
+            None => true,
+        };
+
+        if due {
+            let row = std::mem::replace(&mut self.pending, vec![f32::NEG_INFINITY; frame.len()]);
+            self.history.push_front(row);
+            self.history.truncate(self.history_length);
+            self.last_row = Some(frame.timestamp);
+        }
+
+        due
+    }
+
+    /// Gradient stops along the frequency axis for one row
+    fn color_stops(&self, row: &[f32], extent: f32) -> Vec<(f32, vizia::vg::Color)> {
+        let levels = self
+            .frequencies
+            .iter()
+            .zip(row.iter())
+            .filter(|(frequency, _)| **frequency >= self.min_freq && **frequency <= self.max_freq)
+            .map(|(frequency, magnitude)| {
+                let position = self
+                    .scale
+                    .normalize(*frequency, self.min_freq, self.max_freq);
+                (position, *magnitude)
+            });
+
+        gradient_stops(levels, extent, self.colormap, self.floor)
+    }
+}
+
+impl View for Spectrogram {
+    fn event(&mut self, cx: &mut Context, event: &mut Event) {
+        event.map(|e, _| match e {
+            SpectrogramEvents::Update(frame) => {
+                if frame.is_empty() {
+                    return;
+                }
+
+                // Rows of another resolution or sample rate don't line up with the new ones
+                if frame.len() != self.frequencies.len()
+                    || frame.sample_rate != self.sr
+                    || Some(frame.settings) != self.settings
+                {
+                    if frame.sample_rate != self.sr {
+                        self.sr = frame.sample_rate;
+                        self.update_range();
+                    }
+                    self.reset(frame);
+                }
+
+                if self.add(frame) {
+                    cx.style().needs_redraw = true;
+                }
+            }
+            SpectrogramEvents::UpdateMin(x) => {
+                self.min_normal = *x;
+                self.update_range();
+                cx.style().needs_redraw = true;
+            }
+            SpectrogramEvents::UpdateMax(x) => {
+                self.max_normal = *x;
+                self.update_range();
+                cx.style().needs_redraw = true;
+            }
+            SpectrogramEvents::UpdateHistory(x) => {
+                self.history_length = (*x).max(1);
+                self.history.truncate(self.history_length);
+                cx.style().needs_redraw = true;
+            }
+            SpectrogramEvents::UpdateSpeed(x) => {
+                self.rows_per_second = x.max(0.1);
+            }
+            SpectrogramEvents::UpdateColormap(colormap) => {
+                self.colormap = *colormap;
+                cx.style().needs_redraw = true;
+            }
+            SpectrogramEvents::UpdateFloor(x) => {
+                self.floor = *x;
+                cx.style().needs_redraw = true;
+            }
+            SpectrogramEvents::UpdateScroll(scroll) => {
+                self.scroll = *scroll;
+                cx.style().needs_redraw = true;
+            }
+        });
+    }
+
+    fn draw(&self, cx: &mut DrawContext<'_>, canvas: &mut Canvas) {
+        let entity = cx.current();
+
+        let bounds = cx.cache().get_bounds(entity);
+
+        //Skip spectrograms with no width or no height and ones without data
+        if bounds.w == 0.0 || bounds.h == 0.0 || self.history.is_empty() {
+            return;
+        }
+
+        let width = bounds.w;
+        let height = bounds.h;
+
+        let (time_extent, frequency_extent) = match self.scroll {
+            Scroll::Vertical => (height, width),
+            Scroll::Horizontal => (width, height),
+        };
+        let row_size = time_extent / self.history_length as f32;
+
+        for (age, row) in self.history.iter().enumerate() {
+            let stops = self.color_stops(row, frequency_extent);
+            if stops.is_empty() {
+                continue;
+            }
+
+            // Rows overlap by a bit, so no seams show between them
+            let offset = age as f32 * row_size;
+            let mut path = Path::new();
+            let paint = match self.scroll {
+                Scroll::Vertical => {
+                    path.rect(0., offset, width, row_size + 0.5);
+                    Paint::linear_gradient_stops(0., 0., width, 0., &stops)
+                }
+                Scroll::Horizontal => {
+                    path.rect(width - offset - row_size, 0., row_size + 0.5, height);
+                    Paint::linear_gradient_stops(0., height, 0., 0., &stops)
+                }
+            };
+
+            canvas.fill_path(&mut path, paint);
+        }
+    }
+}
+
+pub trait SpectrogramHandle {
+    fn min(self, val: impl Res<f32>) -> Self;
+    fn max(self, val: impl Res<f32>) -> Self;
+    /// Amount of rows that are kept
+    fn history(self, val: impl Res<usize>) -> Self;
+    /// Rows that get added per second
+    fn speed(self, val: impl Res<f32>) -> Self;
+    fn colormap(self, val: impl Res<Colormap>) -> Self;
+    /// Level in dB that gets the quietest color
+    fn floor(self, val: impl Res<f32>) -> Self;
+    fn scroll(self, val: impl Res<Scroll>) -> Self;
+}
+
+impl SpectrogramHandle for Handle<'_, Spectrogram> {
+    fn min(self, val: impl Res<f32>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, SpectrogramEvents::UpdateMin(value));
+        });
+
+        self
+    }
+
+    fn max(self, val: impl Res<f32>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, SpectrogramEvents::UpdateMax(value));
+        });
+
+        self
+    }
+
+    fn history(self, val: impl Res<usize>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, SpectrogramEvents::UpdateHistory(value));
+        });
+
+        self
+    }
+
+    fn speed(self, val: impl Res<f32>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, SpectrogramEvents::UpdateSpeed(value));
+        });
+
+        self
+    }
+
+    fn colormap(self, val: impl Res<Colormap>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, SpectrogramEvents::UpdateColormap(value));
+        });
+
+        self
+    }
+
+    fn floor(self, val: impl Res<f32>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, SpectrogramEvents::UpdateFloor(value));
+        });
+
+        self
+    }
+
+    fn scroll(self, val: impl Res<Scroll>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, SpectrogramEvents::UpdateScroll(value));
+        });
+
+        self
+    }
+}
+
+impl Data for Scroll {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
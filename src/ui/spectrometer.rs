@@ -1,7 +1,18 @@
 use vizia::prelude::*;
 use vizia::vg::{Paint, Path};
 
+use crate::dsp::{AnalysisSettings, SpectrumFrame};
+use crate::ui::bands::{band_level, octave_bands, Band, Peak};
 use crate::ui::bin::Bin;
+use crate::ui::colormap::{gradient_stops, Colormap};
+use crate::ui::frequency_range;
+
+/// Center of the lowest band, low enough for the 20 Hz band of every resolution
+const LOWEST_BAND: f32 = 16.;
+/// Space between neighbouring bars in pixels
+const BAR_GAP: f32 = 1.;
+/// Height of the peak caps in pixels
+const CAP_HEIGHT: f32 = 2.;
 
 pub struct Spectrometer {
     data: Vec<Bin>,
@@ -10,23 +21,85 @@ pub struct Spectrometer {
     scale: Scale,
     slope: f32,
     col: vizia::vg::Color,
+    /// Colors of the gradient style
+    colormap: Colormap,
+    /// Level that gets the quietest color of the gradient style, everything below looks the same
+    floor: f32,
+    /// The bands of the bars style and their smoothed levels
+    bands: Vec<Band>,
+    band_levels: Vec<Bin>,
+    peaks: Vec<Peak>,
+    /// Whether the bars get caps at their recent peaks
+    peak_hold: bool,
+    /// The settings the frames are analyzed with and how many of their bins a tone covers
+    settings: Option<AnalysisSettings>,
+    bins_per_tone: f32,
+    /// The area below the line of the spectrum style and how opaque it is at the line
+    fill: Fill,
+    fill_opacity: f32,
+    /// Width of the line of the spectrum style in pixels, nothing gets stroked at 0
+    line_width: f32,
     min_freq: f32,
     max_freq: f32,
+    min_normal: f32,
+    max_normal: f32,
+    visible: bool,
+    attack: f32,
+    release: f32,
 }
 
 pub enum VisEvents {
-    Update(Vec<f32>),
+    Update(SpectrumFrame),
     UpdateAttack(f32),
     UpdateRelease(f32),
     UpdateMin(f32),
     UpdateMax(f32),
     UpdateSlope(f32),
+    UpdateVisible(bool),
+    UpdateColormap(Colormap),
+    UpdateFloor(f32),
+    UpdateStyle(Style),
+    UpdatePeakHold(bool),
+    UpdateFill(Fill),
+    UpdateFillOpacity(f32),
+    UpdateLineWidth(f32),
 }
 
-#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum Style {
     Spectrum,
     Gradient,
+    /// Fractional octave bands as bars, with the given amount of bands per octave
+    Bars(usize),
+}
+
+/// What the spectrum style draws below its line, always in the color of the trace
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fill {
+    None,
+    Solid,
+    /// Fades from full opacity at the top of the view to transparent at the bottom, so loud parts stand out
+    Gradient,
+}
+
+impl Fill {
+    pub const COUNT: usize = 3;
+
+    pub fn from_index(idx: usize) -> Self {
+        match idx {
+            0 => Fill::None,
+            1 => Fill::Solid,
+            _ => Fill::Gradient,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Fill::None => "No Fill",
+            Fill::Solid => "Solid Fill",
+            Fill::Gradient => "Gradient Fill",
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -38,7 +111,7 @@ pub enum Scale {
 }
 
 impl Spectrometer {
-    pub fn new<L: Lens<Target = Vec<f32>>>(
+    pub fn new<L: Lens<Target = SpectrumFrame>>(
         cx: &mut Context,
         lens: L,
         sampling_rate: usize,
@@ -46,24 +119,35 @@ impl Spectrometer {
         scale: Scale,
         col: vizia::vg::Color,
     ) -> Handle<Self> {
-        // Build the data vector and precompute all frequencies
-        let mut data = vec![Bin::new(-90.); crate::FFT_SIZE];
-
-        for (i, bin) in data.iter_mut().enumerate() {
-            bin.set_frequency(bin2freq(i, crate::FFT_SIZE, sampling_rate));
-        }
-
-        Self {
-            data,
+        let mut spectrometer = Self {
+            data: Vec::new(),
             sr: sampling_rate,
             style,
             scale,
             slope: 3.0,
             col,
+            colormap: Colormap::Inferno,
+            floor: -90.,
+            bands: Vec::new(),
+            band_levels: Vec::new(),
+            peaks: Vec::new(),
+            peak_hold: false,
+            settings: None,
+            bins_per_tone: 1.,
+            fill: Fill::None,
+            fill_opacity: 0.3,
+            line_width: 2.0,
             min_freq: 20.,
             max_freq: sampling_rate as f32 / 2.,
-        }
-        .build(cx, move |cx| {
+            min_normal: 0.,
+            max_normal: 1.,
+            visible: true,
+            attack: 0.5,
+            release: 0.9,
+        };
+        spectrometer.rebuild_bands();
+
+        spectrometer.build(cx, move |cx| {
             // Bind the input lens to the meter event to update the position
             Binding::new(cx, lens, |cx, value| {
                 cx.emit(VisEvents::Update(value.get(cx)));
@@ -71,24 +155,66 @@ impl Spectrometer {
         })
     }
 
+    /// Rebuilds the bins for a new spectrum length
+    ///
+    /// The smoothing starts over, since the old bins don't line up with the new ones.
+    fn resize(&mut self, bin_amt: usize) {
+        self.data = vec![Bin::new(-90.); bin_amt];
+
+        for bin in self.data.iter_mut() {
+            bin.set_attack(self.attack);
+            bin.set_release(self.release);
+        }
+    }
+
+    /// Lays out the bands of the bars style for the current sample rate
+    ///
+    /// The smoothing and the peaks start over, like the bins do on a resize.
+    fn rebuild_bands(&mut self) {
+        self.bands = match self.style {
+            Style::Bars(bands_per_octave) => {
+                octave_bands(bands_per_octave, LOWEST_BAND, self.sr as f32 / 2.)
+            }
+            _ => Vec::new(),
+        };
+
+        self.band_levels = self
+            .bands
+            .iter()
+            .map(|band| {
+                let mut level = Bin::new(-90.);
+                level.set_frequency(band.center);
+                level.set_attack(self.attack);
+                level.set_release(self.release);
+                level
+            })
+            .collect();
+        self.peaks = vec![Peak::new(-90.); self.bands.len()];
+    }
+
+    /// Where a level ends up between the top at 0 and the bottom at 1, tilted by the slope
+    fn level_position(&self, level: f32, frequency: f32) -> f32 {
+        map(level, 0. - (frequency.log2() * self.slope), -90., 0., 1.).clamp(0., 1.)
+    }
+
+    fn update_range(&mut self) {
+        (self.min_freq, self.max_freq) =
+            frequency_range(self.min_normal, self.max_normal, self.sr as f32);
+    }
+
     fn scale(&self, pos: f32) -> f32 {
+        self.scale.normalize(pos, self.min_freq, self.max_freq)
+    }
+}
+
+impl Scale {
+    /// Where a frequency ends up on an axis from min_freq at 0 to max_freq at 1
+    pub fn normalize(&self, freq: f32, min_freq: f32, max_freq: f32) -> f32 {
         // NOTE: Maybe we can define a function that interpolates between a linear and a log scale
-        match self.scale {
-            Scale::Root(n) => map(
-                pos.powf(n),
-                self.min_freq.powf(n),
-                self.max_freq.powf(n),
-                0.,
-                1.,
-            ),
-            Scale::Logarithmic => map(
-                pos.log2(),
-                self.min_freq.log2(),
-                self.max_freq.log2(),
-                0.,
-                1.,
-            ),
-            Scale::Linear => map(pos, self.min_freq, self.max_freq, 0., 1.),
+        match self {
+            Scale::Root(n) => map(freq.powf(*n), min_freq.powf(*n), max_freq.powf(*n), 0., 1.),
+            Scale::Logarithmic => map(freq.log2(), min_freq.log2(), max_freq.log2(), 0., 1.),
+            Scale::Linear => map(freq, min_freq, max_freq, 0., 1.),
         }
     }
 }
@@ -96,28 +222,117 @@ impl Spectrometer {
 impl View for Spectrometer {
     fn event(&mut self, cx: &mut Context, event: &mut Event) {
         event.map(|e, _| match e {
-            VisEvents::Update(data) => {
-                for (i, val) in data.iter().enumerate() {
-                    self.data[i].update(*val);
+            VisEvents::Update(frame) => {
+                if frame.is_empty() {
+                    return;
+                }
+
+                // The DSP changed its resolution or its backend
+                if frame.len() != self.data.len() {
+                    self.resize(frame.len());
+                }
+
+                // The server changed its sample rate, the smoothed values belong to other frequencies
+                if frame.sample_rate != self.sr {
+                    self.sr = frame.sample_rate;
+                    self.resize(frame.len());
+                    self.update_range();
+                    self.rebuild_bands();
+                }
+
+                for ((bin, frequency), magnitude) in self
+                    .data
+                    .iter_mut()
+                    .zip(frame.frequencies.iter())
+                    .zip(frame.magnitudes.iter())
+                {
+                    bin.set_frequency(*frequency);
+                    bin.update(*magnitude);
+                }
+
+                // Takes a pass over the whole window, so only when the analysis changed
+                if Some(frame.settings) != self.settings {
+                    self.settings = Some(frame.settings);
+                    self.bins_per_tone = frame.settings.bins_per_tone();
+                }
+
+                for ((band, level), peak) in self
+                    .bands
+                    .iter()
+                    .zip(self.band_levels.iter_mut())
+                    .zip(self.peaks.iter_mut())
+                {
+                    level.update(band_level(
+                        band,
+                        &frame.frequencies,
+                        &frame.magnitudes,
+                        self.bins_per_tone,
+                    ));
+                    peak.update(level.get_smooth_val(), frame.timestamp);
                 }
 
                 cx.style().needs_redraw = true;
             }
             VisEvents::UpdateAttack(x) => {
+                self.attack = *x;
                 self.data.iter_mut().for_each(|bin| bin.set_attack(*x));
+                self.band_levels
+                    .iter_mut()
+                    .for_each(|bin| bin.set_attack(*x));
             }
             VisEvents::UpdateRelease(x) => {
+                self.release = *x;
                 self.data.iter_mut().for_each(|bin| bin.set_release(*x));
+                self.band_levels
+                    .iter_mut()
+                    .for_each(|bin| bin.set_release(*x));
             }
             VisEvents::UpdateMin(x) => {
-                self.min_freq = 20. + x * (self.sr as f32 / 4.);
+                self.min_normal = *x;
+                self.update_range();
             }
             VisEvents::UpdateMax(x) => {
-                self.max_freq = (self.sr as f32 / 2.) - (1. - x) * (self.sr as f32 / 2.);
+                self.max_normal = *x;
+                self.update_range();
             }
             VisEvents::UpdateSlope(x) => {
                 self.slope = *x * 4.5;
             }
+            VisEvents::UpdateVisible(x) => {
+                self.visible = *x;
+                cx.style().needs_redraw = true;
+            }
+            VisEvents::UpdateColormap(colormap) => {
+                self.colormap = *colormap;
+                cx.style().needs_redraw = true;
+            }
+            VisEvents::UpdateFloor(x) => {
+                self.floor = *x;
+                cx.style().needs_redraw = true;
+            }
+            VisEvents::UpdateStyle(style) => {
+                if *style != self.style {
+                    self.style = *style;
+                    self.rebuild_bands();
+                    cx.style().needs_redraw = true;
+                }
+            }
+            VisEvents::UpdatePeakHold(x) => {
+                self.peak_hold = *x;
+                cx.style().needs_redraw = true;
+            }
+            VisEvents::UpdateFill(fill) => {
+                self.fill = *fill;
+                cx.style().needs_redraw = true;
+            }
+            VisEvents::UpdateFillOpacity(x) => {
+                self.fill_opacity = x.clamp(0., 1.);
+                cx.style().needs_redraw = true;
+            }
+            VisEvents::UpdateLineWidth(x) => {
+                self.line_width = x.max(0.);
+                cx.style().needs_redraw = true;
+            }
         });
     }
 
@@ -126,8 +341,8 @@ impl View for Spectrometer {
 
         let bounds = cx.cache().get_bounds(entity);
 
-        //Skip meters with no width or no height
-        if bounds.w == 0.0 || bounds.h == 0.0 {
+        //Skip meters with no width or no height, hidden traces and traces without data
+        if bounds.w == 0.0 || bounds.h == 0.0 || !self.visible || self.data.is_empty() {
             return;
         }
 
@@ -140,10 +355,10 @@ impl View for Spectrometer {
 
         match self.style {
             Style::Spectrum => {
-                let mut line_path = Path::new();
+                // Points of the line from left to right, shared by the line and the fill below it
+                let mut points: Vec<(f32, f32)> = Vec::new();
 
                 let mut first_bin_reached = false;
-                let mut last_bin_reached = false;
                 let mut bin_before_first_bin = self.data[0];
 
                 for bin in data {
@@ -159,15 +374,15 @@ impl View for Spectrometer {
                         {
                             // Set the start to the one outside the window
                             // TODO: Interpolate this for the correct value
-                            line_path.move_to(
+                            points.push((
                                 0.,
                                 map(bin_before_first_bin.get_smooth_val(), 0., -90., 0., 1.)
                                     * height,
-                            );
+                            ));
 
                             let position = self.scale(bin.get_frequency()) * width;
                             let y_pos = map(bin.get_smooth_val(), 0., -90., 0., 1.);
-                            line_path.line_to(position, y_pos * height);
+                            points.push((position, y_pos * height));
 
                             first_bin_reached = true;
                         } else {
@@ -175,61 +390,125 @@ impl View for Spectrometer {
                         }
                         continue;
                     }
-                    if !last_bin_reached {
-                        if bin.get_frequency() < self.max_freq {
-                            // Set the start to the one outside the window
-                            // TODO: Interpolate this for the correct value
-                            let position = self.scale(bin.get_frequency()) * width;
-                            let y_pos = map(
-                                bin.get_smooth_val(),
-                                0. - (bin.get_frequency().log2() * self.slope),
-                                -90.,
-                                0.,
-                                1.,
-                            );
-                            line_path.line_to(position, y_pos * height);
-                        } else {
-                            line_path.move_to(
-                                width,
-                                map(
-                                    bin.get_smooth_val(),
-                                    0. - (bin.get_frequency().log2() * self.slope),
-                                    -90.,
-                                    0.,
-                                    1.,
-                                ) * height,
-                            );
-
-                            last_bin_reached = true;
-                        }
+
+                    // The line ends at the last bin in the window
+                    if bin.get_frequency() >= self.max_freq {
+                        break;
                     }
+
+                    // TODO: Interpolate this for the correct value
+                    let position = self.scale(bin.get_frequency()) * width;
+                    let y_pos = map(
+                        bin.get_smooth_val(),
+                        0. - (bin.get_frequency().log2() * self.slope),
+                        -90.,
+                        0.,
+                        1.,
+                    );
+                    points.push((position, y_pos * height));
                 }
 
-                let mut line_paint = Paint::color(self.col);
-                // let mut line_paint = Paint::color(Color::hex("#f54e47"));
-                line_paint.set_line_width(2.0);
+                let (Some(first), Some(last)) = (points.first(), points.last()) else {
+                    return;
+                };
 
-                canvas.stroke_path(&mut line_path, line_paint);
-            }
-            Style::Gradient => {
-                //TODO: Gradient
-                let mut color_vec: Vec<(f32, vizia::vg::Color)> = Vec::new();
-                // Split into 16px wide rectangles that are seperately gradiented
-                // Util function to go [0,1] to bin, since the bins are overfitting
+                if self.fill != Fill::None {
+                    // The area between the line and the bottom, closed straight down at both ends
+                    let mut fill_path = Path::new();
+                    fill_path.move_to(first.0, height);
+                    for (x, y) in points.iter() {
+                        fill_path.line_to(*x, *y);
+                    }
+                    fill_path.line_to(last.0, height);
+                    fill_path.close();
+
+                    let color = |opacity: f32| {
+                        vizia::vg::Color::rgbaf(self.col.r, self.col.g, self.col.b, opacity)
+                    };
+                    let fill_paint = match self.fill {
+                        Fill::Gradient => Paint::linear_gradient(
+                            0.,
+                            0.,
+                            0.,
+                            height,
+                            color(self.fill_opacity),
+                            color(0.),
+                        ),
+                        _ => Paint::color(color(self.fill_opacity)),
+                    };
+
+                    canvas.fill_path(&mut fill_path, fill_paint);
+                }
 
-                for bin in data {
-                    let position = self.scale(bin.get_frequency()) * width;
+                if self.line_width > 0. {
+                    let mut line_path = Path::new();
+                    line_path.move_to(first.0, first.1);
+                    for (x, y) in points.iter().skip(1) {
+                        line_path.line_to(*x, *y);
+                    }
+
+                    let mut line_paint = Paint::color(self.col);
+                    line_paint.set_line_width(self.line_width);
 
-                    color_vec.push((position, gradient_color_map(bin.get_smooth_val())));
+                    canvas.stroke_path(&mut line_path, line_paint);
+                }
+            }
+            Style::Gradient => {
+                // The stops of a gradient go from 0 to 1, so bins outside of the range are left out
+                let levels = data
+                    .iter()
+                    .filter(|bin| {
+                        bin.get_frequency() >= self.min_freq && bin.get_frequency() <= self.max_freq
+                    })
+                    .map(|bin| (self.scale(bin.get_frequency()), bin.get_smooth_val()));
+                let stops = gradient_stops(levels, width, self.colormap, self.floor);
+                if stops.is_empty() {
+                    return;
                 }
 
-                let paint = Paint::linear_gradient_stops(0.0, 0.0, width, 0.0, &color_vec);
+                let paint = Paint::linear_gradient_stops(0.0, 0.0, width, 0.0, &stops);
 
                 let mut path = Path::new();
                 path.rect(0.0, 0.0, width, height);
 
                 canvas.fill_path(&mut path, paint);
             }
+            Style::Bars(_) => {
+                let mut bar_path = Path::new();
+                let mut cap_path = Path::new();
+
+                for ((band, level), peak) in self
+                    .bands
+                    .iter()
+                    .zip(self.band_levels.iter())
+                    .zip(self.peaks.iter())
+                {
+                    if band.high < self.min_freq || band.low > self.max_freq {
+                        continue;
+                    }
+
+                    // Bands at the edges get cut off where the range ends
+                    let left = self.scale(band.low.max(self.min_freq)) * width;
+                    let right = self.scale(band.high.min(self.max_freq)) * width;
+                    let bar_width = (right - left - BAR_GAP).max(1.);
+
+                    let top = self.level_position(level.get_smooth_val(), band.center) * height;
+                    bar_path.rect(left, top, bar_width, height - top);
+
+                    if self.peak_hold {
+                        let cap = self.level_position(peak.level(), band.center) * height;
+                        cap_path.rect(left, cap - CAP_HEIGHT, bar_width, CAP_HEIGHT);
+                    }
+                }
+
+                // Translucent bars, so the bars of other traces still show through
+                let bar_color = vizia::vg::Color::rgbaf(self.col.r, self.col.g, self.col.b, 0.6);
+                canvas.fill_path(&mut bar_path, Paint::color(bar_color));
+
+                if self.peak_hold {
+                    canvas.fill_path(&mut cap_path, Paint::color(self.col));
+                }
+            }
         }
     }
 }
@@ -240,6 +519,19 @@ pub trait SpectrometerHandle {
     fn min(self, val: impl Res<f32>) -> Self;
     fn max(self, val: impl Res<f32>) -> Self;
     fn slope(self, val: impl Res<f32>) -> Self;
+    fn visible(self, val: impl Res<bool>) -> Self;
+    fn style(self, val: impl Res<Style>) -> Self;
+    /// Whether the bars style gets caps at the recent peaks
+    fn peak_hold(self, val: impl Res<bool>) -> Self;
+    /// The area below the line of the spectrum style
+    fn fill(self, val: impl Res<Fill>) -> Self;
+    /// Opacity of the fill from 0 to 1, at the line for the gradient fill
+    fn fill_opacity(self, val: impl Res<f32>) -> Self;
+    /// Width of the line of the spectrum style in pixels
+    fn line_width(self, val: impl Res<f32>) -> Self;
+    fn colormap(self, val: impl Res<Colormap>) -> Self;
+    /// Level in dB that gets the quietest color of the gradient style
+    fn floor(self, val: impl Res<f32>) -> Self;
 }
 
 impl SpectrometerHandle for Handle<'_, Spectrometer> {
@@ -282,13 +574,70 @@ impl SpectrometerHandle for Handle<'_, Spectrometer> {
 
         self
     }
-}
 
-/// Converts the bin index to a frequency in Hz
-///
-/// Source: https://mu.krj.st/spectrm/
-fn bin2freq(bin_idx: usize, bin_amt: usize, sample_rate: usize) -> f32 {
-    bin_idx as f32 * (sample_rate as f32 / (2. * bin_amt as f32))
+    fn visible(self, val: impl Res<bool>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdateVisible(value));
+        });
+
+        self
+    }
+
+    fn style(self, val: impl Res<Style>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdateStyle(value));
+        });
+
+        self
+    }
+
+    fn peak_hold(self, val: impl Res<bool>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdatePeakHold(value));
+        });
+
+        self
+    }
+
+    fn fill(self, val: impl Res<Fill>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdateFill(value));
+        });
+
+        self
+    }
+
+    fn fill_opacity(self, val: impl Res<f32>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdateFillOpacity(value));
+        });
+
+        self
+    }
+
+    fn line_width(self, val: impl Res<f32>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdateLineWidth(value));
+        });
+
+        self
+    }
+
+    fn colormap(self, val: impl Res<Colormap>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdateColormap(value));
+        });
+
+        self
+    }
+
+    fn floor(self, val: impl Res<f32>) -> Self {
+        val.set_or_bind(self.cx, self.entity, |cx, entity, value| {
+            cx.emit_to(entity, VisEvents::UpdateFloor(value));
+        });
+
+        self
+    }
 }
 
 /// Maps [x0,x1] to [y0,y1] linearly at position val in [x0,x1]
@@ -299,9 +648,21 @@ fn map(val: f32, x0: f32, x1: f32, y0: f32, y1: f32) -> f32 {
     y0 + (y1 - y0) * (val - x0) / (x1 - x0)
 }
 
-fn gradient_color_map(val: f32) -> vizia::vg::Color {
-    let col = vizia::vg::Color::rgb((val * 255.) as u8, (val * 255.) as u8, (val * 255.) as u8);
-    if col.r != 0.0 {}
-    col
-    // vizia::vg::Color::white()
+impl Data for Style {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Data for Fill {
+    fn same(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl Data for SpectrumFrame {
+    fn same(&self, other: &Self) -> bool {
+        // Every analysis gets a new timestamp
+        self.timestamp == other.timestamp
+    }
 }